@@ -1,32 +1,309 @@
 use crate::network::NetworkEvent;
-use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use crate::proto::messages::network_message::MessageType;
+use ratatui::crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent,
+    MouseEventKind,
+};
 use std::io;
 
 pub struct CachedPoint {
     pub screen_x: u16,
     pub screen_y: u16,
+    pub sub: u8, // which sample within the cell, column-major
     pub original_u: f64,
     pub map_y: usize,
-    pub intensity: f64,
+    pub normal: (f64, f64, f64), // surface normal facing the viewer, y up
+}
+
+// A message travelling along its sender's arc on the globe, towards us.
+pub struct Pulse {
+    pub from: libp2p::PeerId,
+    pub started: u64, // tick it arrived on
+}
+
+// Ticks a pulse takes to cross its arc.
+pub const PULSE_TICKS: u64 = 15;
+
+// Radians the globe turns per arrow key press, and per cell of mouse drag.
+const CAMERA_STEP: f64 = 0.15;
+const DRAG_RADIANS_PER_CELL: f64 = 0.03;
+
+// Messages kept in memory per room until the user pages further back from the on-disk store.
+pub const DEFAULT_ROOM_BUFFER: usize = 100;
+
+pub const DEFAULT_TIME_FORMAT: &str = "%H:%M";
+
+// How many messages one PageUp/PageDown moves the chat view.
+const SCROLL_STEP: usize = 5;
+
+// Messages the chat pane fits while scrolled back, for keeping the selection on screen.
+const VISIBLE_MESSAGES: usize = 9;
+
+// What '+' reacts with; `/react <emoji>` picks any other.
+const DEFAULT_REACTION: &str = "👍";
+
+#[derive(Clone)]
+pub struct ChatMessage {
+    pub id: String,
+    pub sender: String,
+    pub text: String,
+    pub timestamp: u64,                 // unix millis, as stamped by the sender
+    pub author: Option<libp2p::PeerId>, // signer; only they may edit or retract it
+    pub reply_to: Option<String>,       // id of the message this answers
+    pub edited_at: u64,                 // 0 if never edited
+    pub retracted: bool,
+    pub reactions: std::collections::BTreeMap<String, std::collections::BTreeSet<libp2p::PeerId>>,
+}
+
+impl ChatMessage {
+    pub fn new(id: String, sender: String, text: String, timestamp: u64) -> Self {
+        Self {
+            id,
+            sender,
+            text,
+            timestamp,
+            author: None,
+            reply_to: None,
+            edited_at: 0,
+            retracted: false,
+            reactions: std::collections::BTreeMap::new(),
+        }
+    }
+
+    pub fn system(text: String) -> Self {
+        let timestamp = now_millis();
+        let id = crate::message_id::new_message_id("SYSTEM", timestamp, &text);
+        Self::new(id, "SYSTEM".to_string(), text, timestamp)
+    }
+}
+
+// A joined gossipsub chat room; `name` is the topic string, e.g. "/world".
+// Private rooms subscribe to a key-derived topic, so `label` holds the name the user typed.
+// DM conversations reuse this with a "dm:<peer id>" name.
+pub struct Room {
+    pub name: String,
+    pub label: String,
+    pub private: bool,
+    pub messages: Vec<ChatMessage>, // oldest first
+    pub unread: usize,
+    pub scroll: usize,    // messages hidden below the view; 0 follows the newest
+    pub new_below: usize, // arrived while scrolled back
+    pub first_unread: Option<String>, // id the unread separator is drawn above
+    capacity: usize,
+    exhausted: bool, // nothing older left in the store
+    seen: std::collections::HashSet<String>,
+}
+
+impl Room {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            label: name.to_string(),
+            private: false,
+            messages: Vec::new(),
+            unread: 0,
+            scroll: 0,
+            new_below: 0,
+            first_unread: None,
+            capacity: DEFAULT_ROOM_BUFFER,
+            exhausted: false,
+            seen: std::collections::HashSet::new(),
+        }
+    }
+
+    pub fn private(topic: &str, label: &str) -> Self {
+        Self {
+            label: label.to_string(),
+            private: true,
+            ..Self::new(topic)
+        }
+    }
+
+    pub fn direct(peer: &libp2p::PeerId) -> Self {
+        let full = peer.to_string();
+        Self {
+            label: full[full.len().saturating_sub(8)..].to_string(),
+            ..Self::new(&format!("dm:{}", peer))
+        }
+    }
+
+    // Appends a live message; returns false if it was already in the buffer.
+    pub fn push(&mut self, message: ChatMessage) -> bool {
+        if !self.seen.insert(message.id.clone()) {
+            return false;
+        }
+        self.messages.push(message);
+        // Keep a scrolled-back view anchored on what the user is reading
+        if self.scroll > 0 {
+            self.scroll += 1;
+            self.new_below += 1;
+        }
+        self.trim();
+        true
+    }
+
+    // Merges history into the buffer in timestamp order, skipping messages we already have.
+    // Returns the ones that were new.
+    pub fn merge(&mut self, messages: Vec<ChatMessage>) -> Vec<ChatMessage> {
+        let mut added = Vec::new();
+        for message in messages {
+            if self.seen.insert(message.id.clone()) {
+                added.push(message.clone());
+                self.messages.push(message);
+            }
+        }
+        self.messages.sort_by_key(|m| m.timestamp);
+        self.trim();
+        added
+    }
+
+    // Adds messages paged in from disk in front of the buffer, growing it to hold them.
+    fn prepend_older(&mut self, older: Vec<ChatMessage>) -> usize {
+        let before = self.messages.len();
+        for message in older {
+            if self.seen.insert(message.id.clone()) {
+                self.messages.push(message);
+            }
+        }
+        self.messages.sort_by_key(|m| m.timestamp);
+        self.capacity = self.capacity.max(self.messages.len());
+        self.messages.len() - before
+    }
+
+    pub fn find(&self, id: &str) -> Option<&ChatMessage> {
+        self.messages.iter().find(|m| m.id == id)
+    }
+
+    // Applies a signed reaction, edit or retraction by `author`; returns the updated message.
+    // Actions on messages that aren't in the buffer are dropped.
+    fn apply(&mut self, author: libp2p::PeerId, action: &MessageType) -> Option<ChatMessage> {
+        let target_id = match action {
+            MessageType::Reaction(reaction) => &reaction.target_id,
+            MessageType::Edit(edit) => &edit.target_id,
+            MessageType::Retraction(retraction) => &retraction.target_id,
+            _ => return None,
+        };
+        let message = self.messages.iter_mut().find(|m| &m.id == target_id)?;
+        if message.retracted {
+            return None;
+        }
+
+        match action {
+            MessageType::Reaction(reaction) => {
+                if reaction.emoji.is_empty() || reaction.emoji.len() > 16 {
+                    return None;
+                }
+                let reactors = message.reactions.entry(reaction.emoji.clone()).or_default();
+                let changed = if reaction.removed {
+                    reactors.remove(&author)
+                } else {
+                    reactors.insert(author)
+                };
+                if reactors.is_empty() {
+                    message.reactions.remove(&reaction.emoji);
+                }
+                if !changed {
+                    return None;
+                }
+            }
+            // Only the author may change a message, and a replayed older edit never undoes a newer one
+            MessageType::Edit(edit) => {
+                if message.author != Some(author) || edit.timestamp <= message.edited_at {
+                    return None;
+                }
+                message.text = edit.text.clone();
+                message.edited_at = edit.timestamp;
+            }
+            MessageType::Retraction(_) => {
+                if message.author != Some(author) {
+                    return None;
+                }
+                message.retracted = true;
+                message.text.clear();
+                message.reactions.clear();
+            }
+            _ => return None,
+        }
+        Some(message.clone())
+    }
+
+    // Scrolls just enough to bring message `index` into the chat pane.
+    fn reveal(&mut self, index: usize) {
+        let len = self.messages.len();
+        let end = len - self.scroll; // one past the newest visible message
+        if index >= end {
+            self.scroll = len - index - 1;
+        } else if index + VISIBLE_MESSAGES < end {
+            self.scroll = len - index - VISIBLE_MESSAGES;
+        }
+        if self.scroll == 0 {
+            self.new_below = 0;
+        }
+    }
+
+    // Called when the user opens the room: moves the unread separator to the first unread message.
+    pub fn mark_read(&mut self) {
+        let len = self.messages.len();
+        self.first_unread = (self.unread > 0)
+            .then(|| self.messages.get(len.saturating_sub(self.unread)))
+            .flatten()
+            .map(|m| m.id.clone());
+        self.unread = 0;
+    }
+
+    // Newest message timestamp, used as the starting point for history requests.
+    pub fn latest_timestamp(&self) -> u64 {
+        self.messages
+            .iter()
+            .filter(|m| m.sender != "SYSTEM")
+            .map(|m| m.timestamp)
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn trim(&mut self) {
+        while self.messages.len() > self.capacity {
+            let dropped = self.messages.remove(0); // keep it bounded
+            self.seen.remove(&dropped.id);
+            self.exhausted = false;
+        }
+        self.scroll = self.scroll.min(self.messages.len().saturating_sub(1));
+    }
 }
 
 #[derive(Default)]
 pub struct App {
     pub should_quit: bool,
-    pub rotation_y: f64,
+    pub camera: crate::globe::Camera,
+    pub drag_from: Option<(u16, u16)>, // last mouse position while dragging the globe
+    pub globe_area: ratatui::layout::Rect, // where the globe was last drawn, for the mouse
+    pub mouse_capture: bool,           // applied to the terminal by the main loop
     pub last_width: u16,
     pub last_height: u16,
     pub projection_cache: Vec<CachedPoint>,
+    // What `projection_cache` was built for, besides the size
+    pub projection_detail: crate::globe::Detail,
+    pub projection_tilt: f64,
+    pub projection_zoom: f64,
+    pub projection_view: crate::config::Projection,
+    pub projection: crate::config::Projection, // globe or flat map, as shown
+    pub globe_render: crate::config::GlobeRender,
     pub local_peer_id: Option<libp2p::PeerId>,
     pub peers: Vec<libp2p::PeerId>,
     pub listen_addrs: Vec<libp2p::Multiaddr>,
 
-    pub chat_messages: Vec<(String, String)>, // (sender, text)
+    pub rooms: Vec<Room>,
+    pub active_room: usize,
     pub input_mode: bool,
     pub input_buffer: String,
 
     pub geo_resolver: crate::geo::GeoResolver,
     pub peer_locations: std::collections::HashMap<libp2p::PeerId, (f64, f64, String)>,
+    // What peers said about themselves in presence, preferred over the GeoIP guess; None
+    // for peers that asked not to be placed at all
+    pub declared_locations: std::collections::HashMap<libp2p::PeerId, Option<(f64, f64, String)>>,
+    pub location_share: crate::config::LocationPrecision,
+    pub own_location: Option<(f64, f64)>, // configured, or looked up from our observed address
 
     pub tick_count: u64,
     pub boot_complete: bool,
@@ -35,50 +312,306 @@ pub struct App {
     pub nickname_buffer: String,
 
     pub dialing_peers: std::collections::HashSet<libp2p::PeerId>,
+    pub relayed_peers: std::collections::HashSet<libp2p::PeerId>, // reached only via a circuit
+    pub pulses: Vec<Pulse>,
+
+    pub direct_messages: std::collections::HashMap<libp2p::PeerId, Room>,
+    pub unread_dms: std::collections::HashSet<libp2p::PeerId>,
+    pub selected_peer: usize, // cursor into `peers` in the network panel
+    pub dm_peer: Option<libp2p::PeerId>, // open DM conversation, replaces the global feed
+
+    // Message cursor in the active room, and what the input box is composing for it
+    pub selected_message: Option<String>,
+    pub reply_to: Option<String>,
+    pub editing: Option<String>,
+
+    pub profile: crate::proto::messages::Profile, // ours, as last published
+    pub profiles: std::collections::HashMap<libp2p::PeerId, crate::proto::messages::Profile>,
+
+    pub store: Option<crate::store::MessageStore>,
+    pub time_format: String, // strftime pattern for chat timestamps, in local time
+    pub room_buffer: usize,
+    pub theme: crate::ui::Theme,
+    pub twilight: bool,
 }
 
 impl App {
-    pub fn new() -> Self {
+    pub fn new(geo_resolver: crate::geo::GeoResolver) -> Self {
         Self {
             should_quit: false,
-            rotation_y: 0.0,
+            camera: crate::globe::Camera::default(),
+            drag_from: None,
+            globe_area: ratatui::layout::Rect::default(),
+            mouse_capture: true,
             last_width: 0,
             last_height: 0,
             projection_cache: Vec::new(),
+            projection_detail: crate::globe::Detail::default(),
+            projection_tilt: 0.0,
+            projection_zoom: 0.0,
+            projection_view: crate::config::Projection::default(),
+            projection: crate::config::Projection::default(),
+            globe_render: crate::config::GlobeRender::default(),
             local_peer_id: None,
             peers: Vec::new(),
             listen_addrs: Vec::new(),
-            chat_messages: Vec::new(),
+            rooms: vec![Room::new(crate::network::DEFAULT_ROOM)],
+            active_room: 0,
             input_mode: false,
             input_buffer: String::new(),
-            geo_resolver: crate::geo::GeoResolver::new("GeoLite2-City.mmdb"),
+            geo_resolver,
             peer_locations: std::collections::HashMap::new(),
+            declared_locations: std::collections::HashMap::new(),
+            location_share: crate::config::LocationPrecision::default(),
+            own_location: None,
             tick_count: 0,
             boot_complete: false,
             nickname: None,
             nickname_buffer: String::new(),
             dialing_peers: std::collections::HashSet::new(),
+            relayed_peers: std::collections::HashSet::new(),
+            pulses: Vec::new(),
+            direct_messages: std::collections::HashMap::new(),
+            unread_dms: std::collections::HashSet::new(),
+            selected_peer: 0,
+            dm_peer: None,
+            selected_message: None,
+            reply_to: None,
+            editing: None,
+            profile: crate::proto::messages::Profile::default(),
+            profiles: std::collections::HashMap::new(),
+            store: None,
+            time_format: DEFAULT_TIME_FORMAT.to_string(),
+            room_buffer: DEFAULT_ROOM_BUFFER,
+            theme: crate::ui::Theme::default(),
+            twilight: true,
+        }
+    }
+
+    pub fn set_local_peer(&mut self, peer: libp2p::PeerId) {
+        self.local_peer_id = Some(peer);
+        self.profile = crate::profile::default_for(&peer);
+    }
+
+    // The profile to render `peer` with, if they've published one (ours always exists).
+    pub fn profile_of(&self, peer: &libp2p::PeerId) -> Option<&crate::proto::messages::Profile> {
+        if Some(*peer) == self.local_peer_id {
+            return Some(&self.profile);
+        }
+        self.profiles.get(peer)
+    }
+
+    pub fn publish_profile(
+        &self,
+        cmd_sender: &tokio::sync::mpsc::Sender<crate::network::NetworkCommand>,
+    ) {
+        let _ = cmd_sender.try_send(crate::network::NetworkCommand::PublishProfile(
+            self.profile.clone(),
+        ));
+    }
+
+    // Rejects patterns chrono can't render, which would otherwise panic mid-draw.
+    pub fn set_time_format(&mut self, format: &str) -> Result<(), String> {
+        use chrono::format::{Item, StrftimeItems};
+        if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+            return Err(format!("Invalid time format: {}", format));
+        }
+        self.time_format = format.to_string();
+        Ok(())
+    }
+
+    // Our own position and how precisely to share it, from the `[location]` settings.
+    pub fn set_location(&mut self, location: &crate::config::LocationConfig) {
+        self.location_share = location.share;
+        self.own_location = location.coordinates();
+    }
+
+    // The geohash and hidden flag for our next presence broadcast.
+    pub fn presence_location(&self) -> (String, bool) {
+        match self.location_share.geohash_len() {
+            None => (String::new(), true),
+            Some(len) => (
+                self.own_location
+                    .map(|(lat, lon)| crate::geo::geohash::encode(lat, lon, len))
+                    .unwrap_or_default(),
+                false,
+            ),
+        }
+    }
+
+    // Call before attaching the store, which fills each room up to this size.
+    pub fn set_room_buffer(&mut self, size: usize) {
+        self.room_buffer = size;
+        for room in &mut self.rooms {
+            room.capacity = size;
+            room.trim();
+        }
+    }
+
+    // Starts persisting messages and loads the most recent saved ones into the open rooms.
+    pub fn attach_store(&mut self, store: crate::store::MessageStore) {
+        self.store = Some(store);
+        for idx in 0..self.rooms.len() {
+            let loaded = self.load_saved(&self.rooms[idx].name, None);
+            self.rooms[idx].prepend_older(loaded);
+        }
+    }
+
+    // Reads up to one buffer's worth of saved messages stamped at or before `before`.
+    fn load_saved(&self, key: &str, before: Option<u64>) -> Vec<ChatMessage> {
+        self.store
+            .as_ref()
+            .and_then(|store| store.load_before(key, before, self.room_buffer).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self, key: &str, message: &ChatMessage) {
+        if let Some(store) = &self.store {
+            // A failed write only loses scrollback; never interrupt the chat for it
+            let _ = store.append(key, message);
+        }
+    }
+
+    fn push_to_room(&mut self, idx: usize, message: ChatMessage) -> bool {
+        self.save(&self.rooms[idx].name, &message);
+        self.rooms[idx].push(message)
+    }
+
+    // The DM conversation with `peer`, loaded from disk the first time it's needed.
+    fn conversation(&mut self, peer: libp2p::PeerId) -> &mut Room {
+        if !self.direct_messages.contains_key(&peer) {
+            let mut room = Room::direct(&peer);
+            room.capacity = self.room_buffer;
+            room.prepend_older(self.load_saved(&room.name, None));
+            self.direct_messages.insert(peer, room);
+        }
+        self.direct_messages.get_mut(&peer).unwrap()
+    }
+
+    fn push_to_conversation(&mut self, peer: libp2p::PeerId, message: ChatMessage) -> bool {
+        let key = self.conversation(peer).name.clone();
+        self.save(&key, &message);
+        self.conversation(peer).push(message)
+    }
+
+    // Whatever the chat pane is showing: the open DM conversation or the active room.
+    fn visible_room_mut(&mut self) -> &mut Room {
+        match self.dm_peer {
+            Some(peer) => self.conversation(peer),
+            None => &mut self.rooms[self.active_room],
+        }
+    }
+
+    fn scroll_up(&mut self) {
+        let store_available = self.store.is_some();
+        let room = self.visible_room_mut();
+        // About to run out of buffered messages: page older ones in from disk
+        if room.scroll + 2 * SCROLL_STEP >= room.messages.len()
+            && !room.exhausted
+            && store_available
+        {
+            let key = room.name.clone();
+            let oldest = room.messages.first().map(|m| m.timestamp);
+            let older = self.load_saved(&key, oldest);
+            let room = self.visible_room_mut();
+            if room.prepend_older(older) == 0 {
+                room.exhausted = true;
+            }
+        }
+        let room = self.visible_room_mut();
+        room.scroll = (room.scroll + SCROLL_STEP).min(room.messages.len().saturating_sub(1));
+    }
+
+    fn scroll_down(&mut self) {
+        let room = self.visible_room_mut();
+        room.scroll = room.scroll.saturating_sub(SCROLL_STEP);
+        if room.scroll == 0 {
+            room.new_below = 0;
         }
     }
 
     pub fn tick(&mut self) {
-        // Rotate the globe slowly
-        self.rotation_y = (self.rotation_y + 0.05) % (std::f64::consts::PI * 2.0);
+        // Rotate the globe slowly, or fly to a focused peer
+        self.camera.tick();
         self.tick_count = self.tick_count.wrapping_add(1);
+        let now = self.tick_count;
+        self.pulses
+            .retain(|pulse| now.wrapping_sub(pulse.started) < PULSE_TICKS);
     }
 
     pub fn handle_events(
         &mut self,
         cmd_sender: &mut tokio::sync::mpsc::Sender<crate::network::NetworkCommand>,
     ) -> io::Result<()> {
-        if let Event::Key(key) = event::read()? {
-            if key.kind == KeyEventKind::Press {
+        match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => {
                 self.handle_key(key, cmd_sender);
             }
+            Event::Mouse(mouse) if self.boot_complete => self.handle_mouse(mouse),
+            _ => {}
         }
         Ok(())
     }
 
+    // Dragging turns the globe under the pointer; the wheel zooms.
+    fn handle_mouse(&mut self, mouse: MouseEvent) {
+        let on_globe = self
+            .globe_area
+            .contains(ratatui::layout::Position::new(mouse.column, mouse.row));
+        match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left) if on_globe => {
+                self.drag_from = Some((mouse.column, mouse.row));
+            }
+            MouseEventKind::Drag(MouseButton::Left) => {
+                if let Some((column, row)) = self.drag_from {
+                    let dx = mouse.column as f64 - column as f64;
+                    let dy = mouse.row as f64 - row as f64;
+                    // Cells are about twice as tall as wide
+                    self.camera.rotate(
+                        -dx * DRAG_RADIANS_PER_CELL,
+                        dy * 2.0 * DRAG_RADIANS_PER_CELL,
+                    );
+                    self.drag_from = Some((mouse.column, mouse.row));
+                }
+            }
+            MouseEventKind::Up(MouseButton::Left) => self.drag_from = None,
+            MouseEventKind::ScrollUp if on_globe => self.camera.zoom_in(),
+            MouseEventKind::ScrollDown if on_globe => self.camera.zoom_out(),
+            _ => {}
+        }
+    }
+
+    // Relayed peers get a dashed arc and a weaker signal bar.
+    fn set_relayed(&mut self, peer: libp2p::PeerId, relayed: bool) {
+        if relayed {
+            self.relayed_peers.insert(peer);
+        } else {
+            self.relayed_peers.remove(&peer);
+        }
+    }
+
+    // Sends a pulse down the sender's arc, if they have one on the globe.
+    fn pulse_from(&mut self, author: Option<libp2p::PeerId>) {
+        if let Some(peer) = author.filter(|peer| self.peer_locations.contains_key(peer)) {
+            self.pulses.push(Pulse {
+                from: peer,
+                started: self.tick_count,
+            });
+        }
+    }
+
+    // Centres the globe on the peer selected in the network panel.
+    fn focus_selected_peer(&mut self) {
+        let location = self
+            .peers
+            .get(self.selected_peer)
+            .and_then(|peer| self.peer_locations.get(peer));
+        match location {
+            Some(&(lat, lon, _)) => self.camera.focus(lat, lon),
+            None => self.push_system("No location for that peer yet.".to_string()),
+        }
+    }
+
     fn handle_key(
         &mut self,
         key: KeyEvent,
@@ -88,18 +621,14 @@ impl App {
         if !self.boot_complete {
             match key.code {
                 KeyCode::Enter => {
-                    let trimmed = self.nickname_buffer.trim().to_string();
-                    self.nickname = if trimmed.is_empty() {
-                        None
-                    } else {
-                        Some(trimmed)
-                    };
-                    self.boot_complete = true;
+                    let nickname = self.nickname_buffer.clone();
+                    self.finish_boot(&nickname, cmd_sender);
                 }
-                KeyCode::Char(c) => {
-                    if self.nickname_buffer.len() < 8 {
-                        self.nickname_buffer.push(c);
-                    }
+                KeyCode::Char(c)
+                    if self.nickname_buffer.chars().count()
+                        < crate::profile::MAX_NICKNAME_CHARS =>
+                {
+                    self.nickname_buffer.push(c);
                 }
                 KeyCode::Backspace => {
                     self.nickname_buffer.pop();
@@ -114,20 +643,57 @@ impl App {
                 KeyCode::Enter => {
                     let msg = self.input_buffer.clone();
                     self.input_buffer.clear();
-                    if !msg.is_empty() {
+                    let reply_to = self.reply_to.take();
+                    let editing = self.editing.take();
+                    if msg.starts_with("/join ") || msg == "/leave" || msg.starts_with("/leave ") {
+                        self.run_room_command(&msg, cmd_sender);
+                    } else if ["/nick", "/status", "/avatar", "/color"]
+                        .contains(&msg.split(' ').next().unwrap_or_default())
+                    {
+                        self.run_profile_command(&msg, cmd_sender);
+                    } else if let Some(emoji) = msg.strip_prefix("/react ") {
+                        self.toggle_reaction(emoji.trim(), cmd_sender);
+                    } else if let Some(target_id) = editing {
+                        if !msg.is_empty() {
+                            let action = MessageType::Edit(crate::proto::messages::MessageEdit {
+                                target_id,
+                                text: msg,
+                                timestamp: now_millis(),
+                            });
+                            self.publish_action(action, cmd_sender);
+                        }
+                    } else if !msg.is_empty() {
                         let me = self.display_name();
-                        if let Err(e) =
-                            cmd_sender.try_send(crate::network::NetworkCommand::PublishMessage {
+                        if let Some(peer) = self.dm_peer {
+                            self.send_direct_message(peer, me, msg, cmd_sender);
+                        } else {
+                            let room = self.rooms[self.active_room].name.clone();
+                            let timestamp = now_millis();
+                            let chat = crate::proto::messages::GlobalChat {
                                 sender_id: me.clone(),
                                 text: msg.clone(),
-                            })
-                        {
-                            self.chat_messages.push((
-                                "SYSTEM".to_string(),
-                                format!("Error sending message: {}", e),
-                            ));
-                        } else {
-                            self.chat_messages.push((me, msg));
+                                timestamp,
+                                reply_to: reply_to.clone().unwrap_or_default(),
+                            };
+                            let message_id = self.signed_message_id(&room, MessageType::Chat(chat));
+                            if let Err(e) = cmd_sender.try_send(
+                                crate::network::NetworkCommand::PublishMessage {
+                                    room,
+                                    sender_id: me.clone(),
+                                    text: msg.clone(),
+                                    timestamp,
+                                    reply_to: reply_to.clone(),
+                                },
+                            ) {
+                                self.push_system(format!("Error sending message: {}", e));
+                            } else {
+                                let message = ChatMessage {
+                                    author: self.local_peer_id,
+                                    reply_to,
+                                    ..ChatMessage::new(message_id, me, msg, timestamp)
+                                };
+                                self.push_to_room(self.active_room, message);
+                            }
                         }
                     }
                     self.input_mode = false;
@@ -141,18 +707,428 @@ impl App {
                 KeyCode::Esc => {
                     self.input_mode = false;
                     self.input_buffer.clear();
+                    self.reply_to = None;
+                    self.editing = None;
                 }
                 _ => {}
             }
         } else {
             match key.code {
+                // Esc clears the message cursor, then backs out of a DM conversation, then quits
+                KeyCode::Esc if self.selected_message.is_some() => self.selected_message = None,
+                KeyCode::Esc if self.dm_peer.is_some() => self.dm_peer = None,
                 KeyCode::Char('q') | KeyCode::Esc => self.should_quit = true,
                 KeyCode::Enter => self.input_mode = true,
+                // Globe camera: arrows or hjkl to turn, Shift+arrows or j/k to tilt
+                KeyCode::Left | KeyCode::Char('h') => self.camera.rotate(-CAMERA_STEP, 0.0),
+                KeyCode::Right | KeyCode::Char('l') => self.camera.rotate(CAMERA_STEP, 0.0),
+                KeyCode::Up if key.modifiers.contains(KeyModifiers::SHIFT) => {
+                    self.camera.rotate(0.0, CAMERA_STEP)
+                }
+                KeyCode::Down if key.modifiers.contains(KeyModifiers::SHIFT) => {
+                    self.camera.rotate(0.0, -CAMERA_STEP)
+                }
+                KeyCode::Char('k') => self.camera.rotate(0.0, CAMERA_STEP),
+                KeyCode::Char('j') => self.camera.rotate(0.0, -CAMERA_STEP),
+                KeyCode::Char('=') => self.camera.zoom_in(),
+                KeyCode::Char('-') => self.camera.zoom_out(),
+                KeyCode::Char(' ') => self.camera.spinning = !self.camera.spinning,
+                KeyCode::Char('0') => self.camera.reset(),
+                KeyCode::Char('f') => self.focus_selected_peer(),
+                KeyCode::Char('m') => self.projection = self.projection.next(),
+                KeyCode::Char('c' | 'C') => {
+                    self.mouse_capture = !self.mouse_capture;
+                    self.drag_from = None;
+                    self.push_system(if self.mouse_capture {
+                        "Mouse drags the globe. [C] to select text instead.".to_string()
+                    } else {
+                        "Mouse selects text. [C] to drag the globe again.".to_string()
+                    });
+                }
+                KeyCode::Up => {
+                    self.selected_peer = self.selected_peer.saturating_sub(1);
+                }
+                KeyCode::Down if self.selected_peer + 1 < self.peers.len() => {
+                    self.selected_peer += 1;
+                }
+                KeyCode::Char('d') => {
+                    if let Some(peer) = self.peers.get(self.selected_peer).copied() {
+                        self.conversation(peer).mark_read();
+                        self.dm_peer = Some(peer);
+                        self.unread_dms.remove(&peer);
+                        // The selection points into the room, which the DM pane now hides
+                        self.selected_message = None;
+                    }
+                }
+                // Replies, reactions and edits act on the message picked with [ and ], rooms only
+                KeyCode::Char('[') if self.dm_peer.is_none() => self.move_selection(true),
+                KeyCode::Char(']') if self.dm_peer.is_none() => self.move_selection(false),
+                KeyCode::Char('r') if self.dm_peer.is_none() => {
+                    if let Some(message) = self.selected() {
+                        self.reply_to = Some(message.id.clone());
+                        self.input_mode = true;
+                    }
+                }
+                KeyCode::Char('e') if self.dm_peer.is_none() => {
+                    let own = self.selected().filter(|m| self.is_own(m));
+                    if let Some((id, text)) = own.map(|m| (m.id.clone(), m.text.clone())) {
+                        self.editing = Some(id);
+                        self.input_buffer = text;
+                        self.input_mode = true;
+                    }
+                }
+                KeyCode::Char('x') if self.dm_peer.is_none() => {
+                    if let Some(message) = self.selected().filter(|m| self.is_own(m)) {
+                        let action =
+                            MessageType::Retraction(crate::proto::messages::MessageRetraction {
+                                target_id: message.id.clone(),
+                                timestamp: now_millis(),
+                            });
+                        self.publish_action(action, cmd_sender);
+                    }
+                }
+                KeyCode::Char('+') if self.dm_peer.is_none() => {
+                    self.toggle_reaction(DEFAULT_REACTION, cmd_sender)
+                }
+                KeyCode::PageUp => self.scroll_up(),
+                KeyCode::PageDown => self.scroll_down(),
+                KeyCode::Tab => {
+                    self.switch_room((self.active_room + 1) % self.rooms.len());
+                }
+                KeyCode::BackTab => {
+                    self.switch_room((self.active_room + self.rooms.len() - 1) % self.rooms.len());
+                }
                 _ => {}
             }
         }
     }
 
+    fn switch_room(&mut self, index: usize) {
+        self.active_room = index;
+        self.selected_message = None;
+        self.rooms[index].mark_read();
+        self.dm_peer = None;
+    }
+
+    // Leaves the nickname prompt, either from Enter or `--nickname`. Empty means anonymous.
+    pub fn finish_boot(
+        &mut self,
+        nickname: &str,
+        cmd_sender: &mut tokio::sync::mpsc::Sender<crate::network::NetworkCommand>,
+    ) {
+        let trimmed = nickname.trim().to_string();
+        self.nickname = if trimmed.is_empty() {
+            None
+        } else {
+            Some(trimmed.clone())
+        };
+        self.boot_complete = true;
+        self.update_profile(|profile| profile.nickname = trimmed, cmd_sender);
+    }
+
+    // Joins a public room as if `/join <room>` had been typed.
+    pub fn join_room(
+        &mut self,
+        room: &str,
+        cmd_sender: &mut tokio::sync::mpsc::Sender<crate::network::NetworkCommand>,
+    ) {
+        self.run_room_command(&format!("/join {}", room), cmd_sender);
+    }
+
+    // Handles `/join <room> [passphrase]` and `/leave [room]` typed into the input box.
+    fn run_room_command(
+        &mut self,
+        line: &str,
+        cmd_sender: &mut tokio::sync::mpsc::Sender<crate::network::NetworkCommand>,
+    ) {
+        let mut parts = line.splitn(3, ' ');
+        let command = parts.next().unwrap_or_default();
+        let target = match parts.next().filter(|arg| !arg.is_empty()) {
+            Some(arg) => match normalize_room_name(arg) {
+                Some(name) => Some(name),
+                None => {
+                    self.push_system(format!("Invalid room name: {}", arg));
+                    return;
+                }
+            },
+            None => None,
+        };
+        let passphrase = parts.next().map(str::trim).filter(|p| !p.is_empty());
+
+        if command == "/join" {
+            let target = target.unwrap_or_else(|| self.rooms[self.active_room].label.clone());
+            match passphrase {
+                // Argon2 takes a while; the network task derives the key and reports back
+                Some(passphrase) => {
+                    let command = crate::network::NetworkCommand::DeriveRoomKey {
+                        label: target.clone(),
+                        passphrase: passphrase.to_string(),
+                    };
+                    match cmd_sender.try_send(command) {
+                        Ok(()) => self.push_system(format!("Deriving the key for {}...", target)),
+                        Err(e) => self.push_system(format!("Error joining {}: {}", target, e)),
+                    }
+                }
+                None => self.enter_room(target.clone(), &target, None, cmd_sender),
+            }
+        } else {
+            // A private room's label can match a public room, so a name only picks public
+            // rooms; private ones are left from inside with a bare /leave
+            let found = match &target {
+                Some(target) => self.rooms.iter().position(|r| &r.name == target),
+                None => Some(self.active_room),
+            };
+            let Some(idx) = found else {
+                self.push_system(format!("Not in room {}", target.unwrap_or_default()));
+                return;
+            };
+            let label = self.rooms[idx].label.clone();
+            // The default room carries presence broadcasts, so it can't be left
+            if self.rooms[idx].name == crate::network::DEFAULT_ROOM {
+                self.push_system(format!("Cannot leave {}", label));
+                return;
+            }
+            let topic = self.rooms[idx].name.clone();
+            let _ = cmd_sender.try_send(crate::network::NetworkCommand::LeaveRoom(topic));
+            self.rooms.remove(idx);
+            if idx < self.active_room {
+                // Same room on screen, it just moved down a slot
+                self.active_room -= 1;
+            } else if idx == self.active_room {
+                self.switch_room(idx.min(self.rooms.len() - 1));
+            }
+            self.push_system(format!("Left {}", label));
+        }
+    }
+
+    // Subscribes to `topic` and opens it, or just switches to it if we're already there.
+    // `key` is set for private rooms, whose topic is derived from it.
+    pub fn enter_room(
+        &mut self,
+        topic: String,
+        label: &str,
+        key: Option<crate::e2e::RoomKey>,
+        cmd_sender: &tokio::sync::mpsc::Sender<crate::network::NetworkCommand>,
+    ) {
+        if let Some(idx) = self.rooms.iter().position(|r| r.name == topic) {
+            self.switch_room(idx);
+            return;
+        }
+        let private = key.is_some();
+        if let Err(e) = cmd_sender.try_send(crate::network::NetworkCommand::JoinRoom {
+            room: topic.clone(),
+            key,
+        }) {
+            self.push_system(format!("Error joining {}: {}", label, e));
+            return;
+        }
+        let mut room = if private {
+            Room::private(&topic, label)
+        } else {
+            Room::new(&topic)
+        };
+        room.capacity = self.room_buffer;
+        room.prepend_older(self.load_saved(&topic, None));
+        self.rooms.push(room);
+        self.switch_room(self.rooms.len() - 1);
+        self.push_system(format!("Joined {}", label));
+        // Backfill the new room from everyone we're already connected to
+        for peer in &self.peers {
+            let _ = cmd_sender.try_send(crate::network::NetworkCommand::RequestHistory {
+                peer: *peer,
+                room: topic.clone(),
+                since: 0,
+            });
+        }
+    }
+
+    // The message under the cursor, if it's one that can be replied to or reacted on.
+    fn selected(&self) -> Option<&ChatMessage> {
+        let id = self.selected_message.as_ref()?;
+        self.rooms[self.active_room]
+            .find(id)
+            .filter(|m| m.sender != "SYSTEM" && !m.retracted)
+    }
+
+    fn is_own(&self, message: &ChatMessage) -> bool {
+        message.author.is_some() && message.author == self.local_peer_id
+    }
+
+    // Moves the message cursor one message older or newer, starting from the newest on screen.
+    fn move_selection(&mut self, older: bool) {
+        let room = &mut self.rooms[self.active_room];
+        let current = self
+            .selected_message
+            .as_ref()
+            .and_then(|id| room.messages.iter().position(|m| &m.id == id));
+        let next = match (current, older) {
+            (None, true) => room.messages.len().checked_sub(room.scroll + 1),
+            (None, false) => None,
+            (Some(i), true) => Some(i.saturating_sub(1)),
+            (Some(i), false) => Some(i + 1).filter(|&i| i < room.messages.len()),
+        };
+        self.selected_message = next.map(|i| room.messages[i].id.clone());
+        if let Some(i) = next {
+            room.reveal(i);
+        }
+    }
+
+    fn toggle_reaction(
+        &mut self,
+        emoji: &str,
+        cmd_sender: &mut tokio::sync::mpsc::Sender<crate::network::NetworkCommand>,
+    ) {
+        let Some(me) = self.local_peer_id else {
+            return;
+        };
+        let Some(message) = self.selected() else {
+            self.push_system("Select a message with [ and ] to react to it".to_string());
+            return;
+        };
+        let removed = message
+            .reactions
+            .get(emoji)
+            .is_some_and(|r| r.contains(&me));
+        let action = MessageType::Reaction(crate::proto::messages::Reaction {
+            target_id: message.id.clone(),
+            emoji: emoji.to_string(),
+            removed,
+            timestamp: now_millis(),
+        });
+        self.publish_action(action, cmd_sender);
+    }
+
+    // Sends a reaction, edit or retraction to the active room and applies it locally.
+    fn publish_action(
+        &mut self,
+        action: MessageType,
+        cmd_sender: &mut tokio::sync::mpsc::Sender<crate::network::NetworkCommand>,
+    ) {
+        if let Err(e) = cmd_sender.try_send(crate::network::NetworkCommand::PublishAction {
+            room: self.rooms[self.active_room].name.clone(),
+            action: action.clone(),
+        }) {
+            self.push_system(format!("Error sending message: {}", e));
+            return;
+        }
+        self.apply_action(self.active_room, self.local_peer_id, &action);
+    }
+
+    fn apply_action(&mut self, idx: usize, author: Option<libp2p::PeerId>, action: &MessageType) {
+        // Unsigned actions can't be attributed to anyone, so they're never honoured
+        let Some(author) = author else {
+            return;
+        };
+        if let Some(updated) = self.rooms[idx].apply(author, action) {
+            self.save(&self.rooms[idx].name, &updated);
+        }
+    }
+
+    // Handles `/nick <name>`, `/status [text]`, `/avatar <glyph>` and `/color <#rrggbb>`.
+    fn run_profile_command(
+        &mut self,
+        line: &str,
+        cmd_sender: &mut tokio::sync::mpsc::Sender<crate::network::NetworkCommand>,
+    ) {
+        let (command, arg) = line.split_once(' ').unwrap_or((line, ""));
+        let arg = arg.trim().to_string();
+        match command {
+            "/nick" if !arg.is_empty() => {
+                if self.update_profile(|p| p.nickname = arg.clone(), cmd_sender) {
+                    self.nickname = Some(arg);
+                }
+            }
+            "/status" => {
+                self.update_profile(|p| p.status = arg, cmd_sender);
+            }
+            "/avatar" => {
+                self.update_profile(|p| p.avatar = arg, cmd_sender);
+            }
+            "/color" => match crate::profile::parse_color(&arg) {
+                Some(color) => {
+                    self.update_profile(|p| p.color = color, cmd_sender);
+                }
+                None => self.push_system(format!("Invalid colour {:?}, use #rrggbb", arg)),
+            },
+            _ => self.push_system(format!("Usage: {} <value>", command)),
+        }
+    }
+
+    // Applies a change to our profile and republishes it; returns false if it was invalid.
+    fn update_profile(
+        &mut self,
+        change: impl FnOnce(&mut crate::proto::messages::Profile),
+        cmd_sender: &mut tokio::sync::mpsc::Sender<crate::network::NetworkCommand>,
+    ) -> bool {
+        let mut profile = self.profile.clone();
+        change(&mut profile);
+        if let Err(e) = crate::profile::validate(&profile) {
+            self.push_system(e);
+            return false;
+        }
+        profile.updated_at = now_millis();
+        self.profile = profile;
+        self.publish_profile(cmd_sender);
+        true
+    }
+
+    // System notices land in whichever room is currently on screen, so with a DM open they
+    // go to that conversation.
+    pub fn push_system(&mut self, text: String) {
+        let message = ChatMessage::system(text);
+        match self.dm_peer {
+            Some(peer) => self.push_to_conversation(peer, message),
+            None => self.push_to_room(self.active_room, message),
+        };
+    }
+
+    fn send_direct_message(
+        &mut self,
+        peer: libp2p::PeerId,
+        me: String,
+        text: String,
+        cmd_sender: &mut tokio::sync::mpsc::Sender<crate::network::NetworkCommand>,
+    ) {
+        let timestamp = now_millis();
+        let message_id = self.new_message_id(timestamp, &text);
+        let message = match cmd_sender.try_send(crate::network::NetworkCommand::SendDirectMessage {
+            receiver: peer,
+            message_id: message_id.clone(),
+            sender_id: me.clone(),
+            text: text.clone(),
+        }) {
+            Err(e) => ChatMessage::system(format!("Error sending message: {}", e)),
+            Ok(()) => ChatMessage {
+                author: self.local_peer_id,
+                ..ChatMessage::new(message_id, me, text, timestamp)
+            },
+        };
+        self.push_to_conversation(peer, message);
+    }
+
+    // The ID the network layer will sign `content` with when publishing it to `room`, so our
+    // own copy matches what everyone else receives.
+    fn signed_message_id(&self, room: &str, content: MessageType) -> String {
+        let message = crate::proto::messages::NetworkMessage {
+            author_id: self
+                .local_peer_id
+                .map(|p| p.to_string())
+                .unwrap_or_default(),
+            message_type: Some(content),
+            ..Default::default()
+        };
+        crate::signing::message_id(room, &message).unwrap_or_default()
+    }
+
+    // IDs are derived from our PeerId rather than the display name, which isn't unique.
+    fn new_message_id(&self, timestamp: u64, text: &str) -> String {
+        let sender = self
+            .local_peer_id
+            .map(|p| p.to_string())
+            .unwrap_or_default();
+        crate::message_id::new_message_id(&sender, timestamp, text)
+    }
+
     // Returns the user's display name for chat: nickname if set, truncated PeerID otherwise.
     fn display_name(&self) -> String {
         if let Some(ref nick) = self.nickname {
@@ -176,51 +1152,272 @@ impl App {
             NetworkEvent::Listening(addr) => {
                 self.listen_addrs.push(addr);
             }
-            NetworkEvent::PeerConnected(peer_id, ip) => {
+            NetworkEvent::PeerConnected(peer_id, ip, relayed) => {
+                self.set_relayed(peer_id, relayed);
                 if !self.peers.contains(&peer_id) {
                     self.peers.push(peer_id);
-                    if let Some(loc) = self.geo_resolver.get_fuzzed_location(ip) {
+                    let location = match self.declared_locations.get(&peer_id) {
+                        Some(declared) => declared.clone(),
+                        None => self.geo_resolver.get_fuzzed_location(&peer_id, ip),
+                    };
+                    if let Some(loc) = location {
                         self.peer_locations.insert(peer_id, loc);
                     }
                 }
                 // They are connected, so remove from dialing state if present
                 self.dialing_peers.remove(&peer_id);
             }
+            NetworkEvent::PeerRelayed(peer_id, relayed) => self.set_relayed(peer_id, relayed),
             NetworkEvent::PeerDisconnected(peer_id) => {
                 self.peers.retain(|p| p != &peer_id);
+                self.selected_peer = self.selected_peer.min(self.peers.len().saturating_sub(1));
                 self.peer_locations.remove(&peer_id);
                 self.dialing_peers.remove(&peer_id);
+                self.relayed_peers.remove(&peer_id);
+                self.pulses.retain(|pulse| pulse.from != peer_id);
+            }
+            NetworkEvent::MessageReceived { room, payload } => {
+                // Messages for a room we just left can still be in flight; drop them
+                if let Some(idx) = self.rooms.iter().position(|r| r.name == room) {
+                    match payload.content {
+                        MessageType::Chat(chat) => {
+                            let message = chat_message(payload.message_id, payload.author, chat);
+                            let added = self.push_to_room(idx, message);
+                            if added {
+                                self.pulse_from(payload.author);
+                            }
+                            if added && (idx != self.active_room || self.dm_peer.is_some()) {
+                                self.rooms[idx].unread += 1;
+                            }
+                        }
+                        action => self.apply_action(idx, payload.author, &action),
+                    }
+                }
+            }
+            NetworkEvent::HistoryReceived { room, payloads } => {
+                if let Some(idx) = self.rooms.iter().position(|r| r.name == room) {
+                    let mut messages = Vec::new();
+                    let mut actions = Vec::new();
+                    for payload in payloads {
+                        match payload.content {
+                            MessageType::Chat(chat) => messages.push(chat_message(
+                                payload.message_id,
+                                payload.author,
+                                chat,
+                            )),
+                            action => actions.push((payload.author, action)),
+                        }
+                    }
+                    let added = self.rooms[idx].merge(messages);
+                    for message in &added {
+                        self.save(&self.rooms[idx].name, message);
+                    }
+                    if idx != self.active_room || self.dm_peer.is_some() {
+                        self.rooms[idx].unread += added.len();
+                    }
+                    // After the merge, so actions can find messages from the same batch
+                    for (author, action) in actions {
+                        self.apply_action(idx, author, &action);
+                    }
+                }
+            }
+            NetworkEvent::DirectMessageReceived {
+                peer,
+                message_id,
+                sender_id,
+                text,
+                timestamp,
+            } => {
+                let message = ChatMessage {
+                    author: Some(peer),
+                    ..ChatMessage::new(message_id, sender_id, text, timestamp)
+                };
+                let added = self.push_to_conversation(peer, message);
+                if added {
+                    self.pulse_from(Some(peer));
+                }
+                if added && self.dm_peer != Some(peer) {
+                    self.conversation(peer).unread += 1;
+                    self.unread_dms.insert(peer);
+                }
+            }
+            NetworkEvent::LocationDeclared {
+                peer,
+                geohash,
+                hidden,
+            } => {
+                let declared = if hidden {
+                    None
+                } else {
+                    match crate::geo::declared_location(&geohash) {
+                        Some(location) => Some(location),
+                        // Unreadable, so it's no better than our own guess
+                        None => return,
+                    }
+                };
+                if self.peers.contains(&peer) {
+                    match &declared {
+                        Some(location) => self.peer_locations.insert(peer, location.clone()),
+                        None => self.peer_locations.remove(&peer),
+                    };
+                }
+                self.declared_locations.insert(peer, declared);
             }
-            NetworkEvent::MessageReceived { sender_id, text } => {
-                self.chat_messages.push((sender_id, text));
-                if self.chat_messages.len() > 100 {
-                    self.chat_messages.remove(0); // keep it bounded
+            NetworkEvent::ObservedAddr(ip) => {
+                // The first public address anyone reports is good enough to say roughly where we are
+                if let Some(me) = self.local_peer_id {
+                    if self.own_location.is_none() && crate::geo::is_public(ip) {
+                        self.own_location = self
+                            .geo_resolver
+                            .get_fuzzed_location(&me, ip)
+                            .map(|(lat, lon, _)| (lat, lon));
+                    }
                 }
             }
             NetworkEvent::PeerDiscovered(sender_id, _addrs) => {
-                // Another peer broadcasted their address over the relay!
+                // Learned from a presence broadcast or a DHT lookup
                 if let Ok(peer_id) = sender_id.parse::<libp2p::PeerId>() {
                     // Do we know them?
-                    if !self.peers.contains(&peer_id) && Some(peer_id) != self.local_peer_id {
-                        if !self.dialing_peers.contains(&peer_id) {
-                            self.dialing_peers.insert(peer_id);
-                            self.chat_messages.push((
-                                "SYSTEM".to_string(),
-                                format!("Discovered peer {} via gossip! Dialing...", sender_id),
-                            ));
-                        }
+                    if !self.peers.contains(&peer_id)
+                        && Some(peer_id) != self.local_peer_id
+                        && !self.dialing_peers.contains(&peer_id)
+                    {
+                        self.dialing_peers.insert(peer_id);
+                        self.push_system(format!("Discovered peer {}! Dialing...", sender_id));
                     }
                 }
             }
+            NetworkEvent::ProfileReceived(peer, profile) => {
+                // Gossip can deliver an older copy after a newer one
+                let newer = self
+                    .profiles
+                    .get(&peer)
+                    .is_none_or(|cached| profile.updated_at > cached.updated_at);
+                if newer && crate::profile::validate(&profile).is_ok() {
+                    self.profiles.insert(peer, profile);
+                }
+            }
             NetworkEvent::DialError(peer_id) => {
                 self.dialing_peers.remove(&peer_id);
             }
             NetworkEvent::Error(msg) => {
-                self.chat_messages.push(("SYSTEM".to_string(), msg));
-                if self.chat_messages.len() > 100 {
-                    self.chat_messages.remove(0);
-                }
+                self.push_system(msg);
             }
+            // Joining needs the command channel, so main.rs calls `enter_room` itself
+            NetworkEvent::RoomKeyDerived { .. } => {}
         }
     }
 }
+
+fn chat_message(
+    id: String,
+    author: Option<libp2p::PeerId>,
+    chat: crate::proto::messages::GlobalChat,
+) -> ChatMessage {
+    ChatMessage {
+        author,
+        reply_to: Some(chat.reply_to).filter(|id| !id.is_empty()),
+        ..ChatMessage::new(id, chat.sender_id, chat.text, chat.timestamp)
+    }
+}
+
+fn now_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+// Turns user input like "team" or "/team" into a topic name, rejecting anything unusable.
+pub fn normalize_room_name(input: &str) -> Option<String> {
+    let name = input.trim_start_matches('/');
+    let valid = !name.is_empty()
+        && name.len() <= 32
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    valid.then(|| format!("/{}", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: &str, timestamp: u64) -> ChatMessage {
+        ChatMessage::new(
+            id.to_string(),
+            "alice".to_string(),
+            id.to_string(),
+            timestamp,
+        )
+    }
+
+    fn ids(room: &Room) -> Vec<&str> {
+        room.messages.iter().map(|m| m.id.as_str()).collect()
+    }
+
+    #[test]
+    fn merge_orders_by_timestamp_and_skips_known() {
+        let mut room = Room::new("/world");
+        room.push(message("b", 20));
+        let added = room.merge(vec![message("c", 30), message("a", 10), message("b", 20)]);
+        assert_eq!(
+            added.iter().map(|m| m.id.as_str()).collect::<Vec<_>>(),
+            ["c", "a"]
+        );
+        assert_eq!(ids(&room), ["a", "b", "c"]);
+        assert!(room.merge(vec![message("a", 10)]).is_empty());
+    }
+
+    #[test]
+    fn prepend_older_grows_the_buffer() {
+        let mut room = Room::new("/world");
+        room.capacity = 2;
+        room.push(message("c", 30));
+        room.push(message("d", 40));
+        assert_eq!(
+            room.prepend_older(vec![message("a", 10), message("b", 20), message("c", 30)]),
+            2
+        );
+        assert_eq!(ids(&room), ["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn leaving_an_earlier_room_keeps_the_active_one() {
+        let mut app = App::new(crate::geo::GeoResolver::default());
+        let (mut cmd_sender, _cmd_receiver) = tokio::sync::mpsc::channel(8);
+        for name in ["/a", "/b", "/c"] {
+            app.rooms.push(Room::new(name));
+        }
+        app.switch_room(3);
+
+        app.run_room_command("/leave /a", &mut cmd_sender);
+        assert_eq!(app.rooms[app.active_room].name, "/c");
+        app.run_room_command("/leave /c", &mut cmd_sender);
+        assert_eq!(app.rooms[app.active_room].name, "/b");
+        app.run_room_command("/leave", &mut cmd_sender);
+        assert_eq!(
+            app.rooms[app.active_room].name,
+            crate::network::DEFAULT_ROOM
+        );
+    }
+
+    #[test]
+    fn notices_follow_the_visible_pane() {
+        let mut app = App::new(crate::geo::GeoResolver::default());
+        let peer = libp2p::PeerId::random();
+        app.push_system("in the room".to_string());
+        app.dm_peer = Some(peer);
+        app.push_system("in the dm".to_string());
+
+        let texts = |room: &Room| {
+            room.messages
+                .iter()
+                .map(|m| m.text.clone())
+                .collect::<Vec<_>>()
+        };
+        assert!(texts(&app.rooms[0]).contains(&"in the room".to_string()));
+        assert!(!texts(&app.rooms[0]).contains(&"in the dm".to_string()));
+        assert_eq!(texts(app.conversation(peer)), ["in the dm"]);
+    }
+}
@@ -1,19 +1,41 @@
 use futures::StreamExt;
 use libp2p::{
-    identify, identity, noise, ping, relay,
+    identify, kad, noise, ping, relay,
     swarm::{NetworkBehaviour, SwarmEvent},
-    tcp, yamux, Multiaddr, SwarmBuilder,
+    tcp, yamux, Multiaddr, StreamProtocol, SwarmBuilder,
 };
 use std::error::Error;
 use std::time::Duration;
 use tracing_subscriber::EnvFilter;
 
+#[path = "../codec.rs"]
+mod codec;
+#[path = "../history.rs"]
+mod history;
+#[path = "../keystore.rs"]
+mod keystore;
+#[allow(dead_code)] // only the gossipsub side is used here
+#[path = "../message_id.rs"]
+mod message_id;
+// Generated types; the relay only uses the history ones
+#[allow(dead_code)]
+#[path = "../proto/mod.rs"]
+mod proto;
+
+// Always subscribed; any other room is joined only while some client is in it.
+const DEFAULT_ROOM: &str = "/world";
+
+// Must match the clients' DHT protocol so they can bootstrap from us.
+const KAD_PROTOCOL: &str = "/terra-link/kad/1.0.0";
+
 #[derive(NetworkBehaviour)]
 struct RelayBehaviour {
     relay: relay::Behaviour,
     ping: ping::Behaviour,
     identify: identify::Behaviour,
     gossipsub: libp2p::gossipsub::Behaviour,
+    history: history::HistoryBehaviour,
+    kademlia: kad::Behaviour<kad::store::MemoryStore>,
 }
 
 #[tokio::main]
@@ -22,12 +44,32 @@ async fn main() -> Result<(), Box<dyn Error>> {
         .with_env_filter(EnvFilter::from_default_env())
         .init();
 
+    // A stable keypair keeps the RELAY_NODE multiaddr (which embeds the PeerId) valid across restarts
+    let args: Vec<String> = std::env::args().collect();
+    let identity_path = args
+        .iter()
+        .position(|a| a == "--identity")
+        .and_then(|i| args.get(i + 1))
+        .map(std::path::PathBuf::from)
+        .unwrap_or_else(|| keystore::default_identity_path("relay"));
+
+    if args.get(1).map(String::as_str) == Some("identity") {
+        let keypair = match args.get(2).map(String::as_str) {
+            Some("rotate") => keystore::rotate(&identity_path)?,
+            _ => keystore::load_or_create(&identity_path)?,
+        };
+        println!("Identity file: {}", identity_path.display());
+        println!("Peer ID: {}", keypair.public().to_peer_id());
+        return Ok(());
+    }
+
     println!("Starting Terra-Link Dedicated Relay Server...");
 
-    let local_key = identity::Keypair::generate_ed25519();
+    let local_key = keystore::load_or_create(&identity_path)?;
     let local_peer_id = local_key.public().to_peer_id();
 
     println!("Local Peer ID: {}", local_peer_id);
+    println!("Identity file: {}", identity_path.display());
 
     let mut swarm = SwarmBuilder::with_existing_identity(local_key.clone())
         .with_tokio()
@@ -56,18 +98,10 @@ async fn main() -> Result<(), Box<dyn Error>> {
             };
 
             // Setup Gossipsub config
-            let message_id_fn = |message: &libp2p::gossipsub::Message| {
-                use std::collections::hash_map::DefaultHasher;
-                use std::hash::{Hash, Hasher};
-                let mut s = DefaultHasher::new();
-                message.data.hash(&mut s);
-                libp2p::gossipsub::MessageId::from(s.finish().to_string())
-            };
-
             let gossipsub_config = libp2p::gossipsub::ConfigBuilder::default()
                 .heartbeat_interval(Duration::from_secs(1))
                 .validation_mode(libp2p::gossipsub::ValidationMode::Strict)
-                .message_id_fn(message_id_fn)
+                .message_id_fn(message_id::gossip_message_id)
                 .build()
                 .expect("Valid config");
 
@@ -77,11 +111,21 @@ async fn main() -> Result<(), Box<dyn Error>> {
             )
             .expect("Valid behaviour");
 
+            let mut kademlia = kad::Behaviour::with_config(
+                local_peer_id,
+                kad::store::MemoryStore::new(local_peer_id),
+                kad::Config::new(StreamProtocol::new(KAD_PROTOCOL)),
+            );
+            // We're publicly reachable, so answer DHT queries without waiting for AutoNAT
+            kademlia.set_mode(Some(kad::Mode::Server));
+
             RelayBehaviour {
                 relay: relay::Behaviour::new(local_peer_id, relay_config),
                 ping: ping::Behaviour::default(),
                 identify,
                 gossipsub,
+                history: history::new_behaviour(),
+                kademlia,
             }
         })?
         // Note: Ping determines if the connection is dead. We do not want an arbitrary idle timeout closing active relayed tunnels.
@@ -96,7 +140,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let quic_addr: Multiaddr = "/ip4/0.0.0.0/udp/4002/quic-v1".parse()?;
     swarm.listen_on(quic_addr)?;
 
-    let topic = libp2p::gossipsub::IdentTopic::new("/world");
+    let topic = libp2p::gossipsub::IdentTopic::new(DEFAULT_ROOM);
     swarm.behaviour_mut().gossipsub.subscribe(&topic)?;
 
     println!("Relay listening for UDP (QUIC) and TCP connections on port 4001...");
@@ -108,6 +152,9 @@ async fn main() -> Result<(), Box<dyn Error>> {
         local_peer_id
     );
 
+    // The relay is always online, so it is the best place for late joiners to catch up from
+    let mut history_store = history::HistoryStore::default();
+
     loop {
         match swarm.select_next_some().await {
             SwarmEvent::NewListenAddr { address, .. } => {
@@ -116,7 +163,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
             SwarmEvent::Behaviour(RelayBehaviourEvent::Relay(event)) => {
                 println!("Relay circuit event: {:?}", event);
             }
-            SwarmEvent::Behaviour(RelayBehaviourEvent::Ping(event)) => {
+            SwarmEvent::Behaviour(RelayBehaviourEvent::Ping(_event)) => {
                 // Commenting out to avoid log spam, but ping keeps the connection alive
                 // println!("Ping event: {:?}", event);
             }
@@ -125,7 +172,70 @@ async fn main() -> Result<(), Box<dyn Error>> {
             }
             SwarmEvent::ConnectionClosed { peer_id, cause, .. } => {
                 println!("Disconnected from {} (cause: {:?})", peer_id, cause);
+                leave_unused_rooms(&mut swarm);
             }
+            SwarmEvent::Behaviour(RelayBehaviourEvent::Gossipsub(
+                libp2p::gossipsub::Event::Subscribed { peer_id, topic },
+            )) => {
+                // Join rooms on demand so the relay keeps forwarding between NATed members.
+                // Room topics are IdentTopics, whose hash is the room name itself.
+                let room = libp2p::gossipsub::IdentTopic::new(topic.as_str());
+                if let Ok(true) = swarm.behaviour_mut().gossipsub.subscribe(&room) {
+                    println!("Peer {} joined {}, relay subscribed", peer_id, topic);
+                }
+            }
+            SwarmEvent::Behaviour(RelayBehaviourEvent::Gossipsub(
+                libp2p::gossipsub::Event::Unsubscribed { .. },
+            )) => {
+                leave_unused_rooms(&mut swarm);
+            }
+            SwarmEvent::Behaviour(RelayBehaviourEvent::Gossipsub(
+                libp2p::gossipsub::Event::Message { message, .. },
+            )) => {
+                history_store.record(message.topic.as_str(), &message.data);
+            }
+            SwarmEvent::Behaviour(RelayBehaviourEvent::History(
+                libp2p::request_response::Event::Message {
+                    peer,
+                    message:
+                        libp2p::request_response::Message::Request {
+                            request, channel, ..
+                        },
+                    ..
+                },
+            )) => {
+                let response = history_store.query(&request);
+                println!(
+                    "Serving {} history messages for {} to {}",
+                    response.payloads.len(),
+                    request.room,
+                    peer
+                );
+                let _ = swarm
+                    .behaviour_mut()
+                    .history
+                    .send_response(channel, response);
+            }
+            SwarmEvent::Behaviour(RelayBehaviourEvent::Identify(identify::Event::Received {
+                peer_id,
+                info,
+                ..
+            })) => {
+                // Remember every DHT server that connects so later clients can find it through us
+                if info.protocols.iter().any(|p| p.as_ref() == KAD_PROTOCOL) {
+                    for addr in info.listen_addrs {
+                        swarm.behaviour_mut().kademlia.add_address(&peer_id, addr);
+                    }
+                }
+            }
+            SwarmEvent::Behaviour(RelayBehaviourEvent::Kademlia(kad::Event::RoutingUpdated {
+                peer,
+                is_new_peer: true,
+                ..
+            })) => {
+                println!("Added {} to the DHT routing table", peer);
+            }
+            SwarmEvent::Behaviour(RelayBehaviourEvent::Kademlia(_)) => {}
             SwarmEvent::IncomingConnectionError { error, .. } => {
                 println!("Incoming connection error: {:?}", error);
             }
@@ -139,3 +249,26 @@ async fn main() -> Result<(), Box<dyn Error>> {
         }
     }
 }
+
+// Unsubscribe from every non-default room that no connected peer is subscribed to any more.
+fn leave_unused_rooms(swarm: &mut libp2p::Swarm<RelayBehaviour>) {
+    let gossipsub = &swarm.behaviour().gossipsub;
+    let unused: Vec<String> = gossipsub
+        .topics()
+        .filter(|topic| topic.as_str() != DEFAULT_ROOM)
+        .filter(|topic| {
+            !gossipsub
+                .all_peers()
+                .any(|(_, topics)| topics.contains(topic))
+        })
+        .map(|topic| topic.to_string())
+        .collect();
+
+    for room in unused {
+        swarm
+            .behaviour_mut()
+            .gossipsub
+            .unsubscribe(&libp2p::gossipsub::IdentTopic::new(&room));
+        println!("No peers left in {}, relay unsubscribed", room);
+    }
+}
@@ -0,0 +1,122 @@
+use clap::{Parser, Subcommand};
+use libp2p::Multiaddr;
+use std::path::PathBuf;
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "terra-link",
+    version,
+    about = "Peer-to-peer chat on a spinning globe"
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Address to listen on, e.g. /ip4/0.0.0.0/tcp/4001 (repeatable)
+    #[arg(long, value_name = "MULTIADDR")]
+    pub listen: Vec<Multiaddr>,
+
+    /// Peer address to dial at startup (repeatable)
+    #[arg(long, value_name = "MULTIADDR")]
+    pub dial: Vec<Multiaddr>,
+
+    /// Relay to reserve a circuit on [default: $RELAY_NODE]
+    #[arg(long, value_name = "MULTIADDR")]
+    pub relay: Option<Multiaddr>,
+
+    /// Skip the nickname prompt and use this one
+    #[arg(long, value_parser = parse_nickname)]
+    pub nickname: Option<String>,
+
+    /// Config file to use instead of the XDG ones
+    #[arg(long, value_name = "FILE", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Identity keypair file
+    #[arg(long, value_name = "FILE", global = true)]
+    pub identity: Option<PathBuf>,
+
+    /// GeoLite2 City database [default: GeoLite2-City.mmdb in the data dir]
+    #[arg(long, value_name = "FILE")]
+    pub geo_db: Option<PathBuf>,
+
+    /// How precisely to tell other peers where we are
+    #[arg(long, value_name = "LEVEL")]
+    pub share_location: Option<crate::config::LocationPrecision>,
+
+    /// Extra room to join at startup (repeatable)
+    #[arg(long, value_name = "NAME", value_parser = parse_room)]
+    pub room: Vec<String>,
+
+    /// Run headless, printing events instead of drawing the globe
+    #[arg(long)]
+    pub no_tui: bool,
+
+    /// Log filter, e.g. "info" or "terra_link=debug,libp2p=warn" [default: $RUST_LOG or warn]
+    #[arg(long, value_name = "FILTER")]
+    pub log_level: Option<String>,
+
+    /// Discover peers on the local network via mDNS
+    #[arg(long)]
+    pub mdns: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Show or replace the identity keypair
+    Identity {
+        #[command(subcommand)]
+        action: Option<IdentityAction>,
+    },
+    /// Manage the GeoLite database used to place peers on the globe
+    Geo {
+        #[command(subcommand)]
+        action: GeoAction,
+    },
+    /// Inspect the layered configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Subcommand)]
+pub enum ConfigAction {
+    /// Print the effective configuration as TOML
+    Show,
+}
+
+#[derive(Debug, Clone, Copy, Subcommand)]
+pub enum GeoAction {
+    /// Download (or resume downloading) the database and install it once verified
+    Update {
+        /// Install even when no geo.sha256 or geo.sha256_url is configured to verify against
+        #[arg(long)]
+        allow_unverified: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Subcommand)]
+pub enum IdentityAction {
+    /// Print the PeerId of the stored keypair (default)
+    Show,
+    /// Generate a new keypair, changing our PeerId
+    Rotate,
+}
+
+// Same rules as `/nick`, checked up front so a bad value fails before the TUI starts.
+pub fn parse_nickname(value: &str) -> Result<String, String> {
+    let nickname = value.trim().to_string();
+    let profile = crate::proto::messages::Profile {
+        nickname: nickname.clone(),
+        avatar: crate::profile::DEFAULT_AVATAR.to_string(),
+        ..Default::default()
+    };
+    crate::profile::validate(&profile)?;
+    Ok(nickname)
+}
+
+pub fn parse_room(value: &str) -> Result<String, String> {
+    crate::app::normalize_room_name(value)
+        .ok_or_else(|| "room names are up to 32 letters, digits, '-' or '_'".to_string())
+}
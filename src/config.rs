@@ -0,0 +1,612 @@
+use crate::cli::Cli;
+use crate::ui::Theme;
+use libp2p::Multiaddr;
+use ratatui::style::Color;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+const APP_DIR: &str = "terra-link";
+const FILE_NAME: &str = "config.toml";
+
+// Every setting, layered as defaults < config files < environment < command line.
+//
+// Addresses and colours stay strings here so `config show` prints them as written; the
+// typed getters parse them and name the offending key when one is bad.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub network: NetworkConfig,
+    pub chat: ChatConfig,
+    pub storage: StorageConfig,
+    pub geo: GeoConfig,
+    pub location: LocationConfig,
+    pub globe: GlobeConfig,
+    pub theme: ThemeConfig,
+    // Files that contributed, lowest precedence first
+    #[serde(skip)]
+    pub sources: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConfig {
+    pub listen: Vec<String>,
+    pub dial: Vec<String>,
+    pub relay: Option<String>,
+    pub bootstrap: Vec<String>,
+    pub mdns: bool,
+    pub presence_interval_secs: u64,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            listen: Vec::new(),
+            dial: Vec::new(),
+            relay: None,
+            bootstrap: Vec::new(),
+            mdns: false,
+            presence_interval_secs: 15,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChatConfig {
+    pub nickname: Option<String>,
+    pub rooms: Vec<String>, // joined at startup, besides the default room
+    pub room_buffer: usize, // messages kept in memory per room
+    pub time_format: String,
+}
+
+impl Default for ChatConfig {
+    fn default() -> Self {
+        Self {
+            nickname: None,
+            rooms: Vec::new(),
+            room_buffer: crate::app::DEFAULT_ROOM_BUFFER,
+            time_format: crate::app::DEFAULT_TIME_FORMAT.to_string(),
+        }
+    }
+}
+
+// 0 means unlimited.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub retention_days: u64,
+    pub retention_messages: u64,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        let policy = crate::store::RetentionPolicy::default();
+        Self {
+            retention_days: policy.max_age_days.unwrap_or(0),
+            retention_messages: policy.max_messages.unwrap_or(0) as u64,
+        }
+    }
+}
+
+// `sha256` pins the expected database hash; `sha256_url` fetches it alongside instead.
+// `manual` entries are consulted before `provider`, whichever it is. `fuzz_km` is the
+// offset radius, or the cell size when snapping to a grid.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GeoConfig {
+    pub provider: GeoBackend,
+    pub fuzz: FuzzMode,
+    pub fuzz_km: f64,
+    pub db_path: PathBuf,
+    pub source_url: String,
+    pub sha256: Option<String>,
+    pub sha256_url: Option<String>,
+    pub manual: Vec<ManualLocation>,
+}
+
+impl Default for GeoConfig {
+    fn default() -> Self {
+        Self {
+            provider: GeoBackend::default(),
+            fuzz: FuzzMode::default(),
+            fuzz_km: 50.0,
+            db_path: crate::geo_db::default_path(),
+            source_url: crate::geo_db::DEFAULT_SOURCE_URL.to_string(),
+            sha256: None,
+            sha256_url: None,
+            manual: Vec::new(),
+        }
+    }
+}
+
+// `maxmind-country` takes a GeoLite2 Country database at `db_path`; `builtin` needs no
+// database but places peers only on their country, or failing that their continent;
+// `manual` places only the networks listed under `geo.manual`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GeoBackend {
+    #[default]
+    MaxmindCity,
+    MaxmindCountry,
+    Builtin,
+    Manual,
+}
+
+// How GeoIP coordinates are blurred before being drawn; see `geo::Fuzzer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FuzzMode {
+    #[default]
+    Offset,
+    Grid,
+    Off,
+}
+
+// One `[[geo.manual]]` entry: a network ("10.1.0.0/16" or a single address) pinned to
+// `lat`/`lon`, or to the centre of `country` (ISO code).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ManualLocation {
+    pub network: String,
+    pub lat: Option<f64>,
+    pub lon: Option<f64>,
+    pub country: Option<String>,
+    pub label: Option<String>,
+}
+
+// What we tell other peers about where we are. Without `lat`/`lon` our own position is
+// looked up from the public address peers observe us on.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LocationConfig {
+    pub share: LocationPrecision,
+    pub lat: Option<f64>,
+    pub lon: Option<f64>,
+}
+
+impl LocationConfig {
+    pub fn coordinates(&self) -> Option<(f64, f64)> {
+        self.lat.zip(self.lon)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum LocationPrecision {
+    Exact,
+    #[default]
+    City,
+    Country,
+    Continent,
+    Hidden,
+}
+
+impl LocationPrecision {
+    // Geohash characters published at this level: 8 is ~20 m, 4 ~20 km, 2 ~600 km and
+    // 1 ~2500 km across.
+    pub fn geohash_len(self) -> Option<usize> {
+        match self {
+            LocationPrecision::Exact => Some(8),
+            LocationPrecision::City => Some(4),
+            LocationPrecision::Country => Some(2),
+            LocationPrecision::Continent => Some(1),
+            LocationPrecision::Hidden => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GlobeConfig {
+    pub render: GlobeRender,
+    pub twilight: bool,         // dusk band along the day/night terminator
+    pub projection: Projection, // the view to start in; [M] switches at runtime
+    // Drag and scroll the globe. Off keeps the terminal's own text selection; [C] toggles.
+    pub mouse: bool,
+}
+
+impl Default for GlobeConfig {
+    fn default() -> Self {
+        Self {
+            render: GlobeRender::default(),
+            twilight: true,
+            projection: Projection::default(),
+            mouse: true,
+        }
+    }
+}
+
+// The orthographic globe shows one hemisphere at a time; the flat maps show every peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Projection {
+    #[default]
+    Globe,
+    Equirectangular,
+    Robinson,
+}
+
+impl Projection {
+    pub fn flat(self) -> Option<crate::globe::FlatMap> {
+        match self {
+            Projection::Globe => None,
+            Projection::Equirectangular => Some(crate::globe::FlatMap::Equirectangular),
+            Projection::Robinson => Some(crate::globe::FlatMap::Robinson),
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Projection::Globe => Projection::Equirectangular,
+            Projection::Equirectangular => Projection::Robinson,
+            Projection::Robinson => Projection::Globe,
+        }
+    }
+}
+
+// `auto` draws braille on terminals wide enough for it and the plain renderer otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GlobeRender {
+    #[default]
+    Auto,
+    Ascii,
+    HalfBlock,
+    Braille,
+}
+
+impl GlobeRender {
+    // Below this many columns sub-cell detail is mostly noise
+    const AUTO_MIN_WIDTH: u16 = 100;
+
+    pub fn detail(self, width: u16) -> crate::globe::Detail {
+        match self {
+            GlobeRender::Auto if width >= Self::AUTO_MIN_WIDTH => crate::globe::Detail::Braille,
+            GlobeRender::Auto | GlobeRender::Ascii => crate::globe::Detail::Ascii,
+            GlobeRender::HalfBlock => crate::globe::Detail::HalfBlock,
+            GlobeRender::Braille => crate::globe::Detail::Braille,
+        }
+    }
+}
+
+// "#rrggbb" for each role in `ui::Theme`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeConfig {
+    pub primary: String,
+    pub accent: String,
+    pub highlight: String,
+    pub secondary: String,
+    pub dim: String,
+    pub text: String,
+    pub background: String,
+}
+
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        let theme = Theme::default();
+        Self {
+            primary: hex(theme.primary),
+            accent: hex(theme.accent),
+            highlight: hex(theme.highlight),
+            secondary: hex(theme.secondary),
+            dim: hex(theme.dim),
+            text: hex(theme.text),
+            background: hex(theme.background),
+        }
+    }
+}
+
+impl Config {
+    // Reads `explicit` if given, otherwise whichever XDG config files exist, then applies
+    // the environment on top.
+    pub fn load(explicit: Option<&Path>) -> Result<Self, String> {
+        let mut merged = toml::Table::new();
+        let mut sources = Vec::new();
+        let paths = match explicit {
+            Some(path) => vec![path.to_path_buf()],
+            None => search_paths(),
+        };
+        for path in paths {
+            let text = match std::fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound && explicit.is_none() => {
+                    continue
+                }
+                Err(e) => return Err(format!("{}: {}", path.display(), e)),
+            };
+            let table: toml::Table =
+                toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+            merge(&mut merged, table);
+            sources.push(path);
+        }
+
+        let mut config: Config = merged.try_into().map_err(|e| {
+            let files: Vec<String> = sources.iter().map(|p| p.display().to_string()).collect();
+            format!("{}: {}", files.join(", "), e)
+        })?;
+        config.sources = sources;
+        config.apply_env()?;
+        Ok(config)
+    }
+
+    // The environment variables Terra-Link has always honoured, plus the TERRA_LINK_GEO_* ones.
+    fn apply_env(&mut self) -> Result<(), String> {
+        if let Ok(relay) = std::env::var("RELAY_NODE") {
+            self.network.relay = Some(relay);
+        }
+        if let Ok(list) = std::env::var("TERRA_LINK_BOOTSTRAP") {
+            self.network.bootstrap = list
+                .split(',')
+                .map(str::trim)
+                .filter(|e| !e.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+        if let Ok(format) = std::env::var("TERRA_LINK_TIME_FORMAT") {
+            self.chat.time_format = format;
+        }
+        if let Some(days) = env_number("TERRA_LINK_RETENTION_DAYS")? {
+            self.storage.retention_days = days;
+        }
+        if let Some(count) = env_number("TERRA_LINK_RETENTION_MESSAGES")? {
+            self.storage.retention_messages = count;
+        }
+        if let Ok(path) = std::env::var("TERRA_LINK_GEO_DB") {
+            self.geo.db_path = PathBuf::from(path);
+        }
+        if let Ok(url) = std::env::var("TERRA_LINK_GEO_URL") {
+            self.geo.source_url = url;
+        }
+        Ok(())
+    }
+
+    // Flags the user passed override everything else.
+    pub fn apply_cli(&mut self, cli: &Cli) {
+        if !cli.listen.is_empty() {
+            self.network.listen = cli.listen.iter().map(|a| a.to_string()).collect();
+        }
+        if !cli.dial.is_empty() {
+            self.network.dial = cli.dial.iter().map(|a| a.to_string()).collect();
+        }
+        if let Some(relay) = &cli.relay {
+            self.network.relay = Some(relay.to_string());
+        }
+        if cli.mdns {
+            self.network.mdns = true;
+        }
+        if let Some(nickname) = &cli.nickname {
+            self.chat.nickname = Some(nickname.clone());
+        }
+        if !cli.room.is_empty() {
+            self.chat.rooms = cli.room.clone();
+        }
+        if let Some(path) = &cli.geo_db {
+            self.geo.db_path = path.clone();
+        }
+        if let Some(share) = cli.share_location {
+            self.location.share = share;
+        }
+    }
+
+    // Parses every typed setting once, so a bad file fails at startup with the key named.
+    pub fn validate(&self) -> Result<(), String> {
+        self.network.listen_addrs()?;
+        self.network.dial_addrs()?;
+        self.network.relay_addr()?;
+        self.network.bootstrap_addrs()?;
+        if self.network.presence_interval_secs == 0 {
+            return Err("network.presence_interval_secs must be at least 1".to_string());
+        }
+        if let Some(nickname) = &self.chat.nickname {
+            crate::cli::parse_nickname(nickname).map_err(|e| format!("chat.nickname: {}", e))?;
+        }
+        for room in &self.chat.rooms {
+            crate::cli::parse_room(room).map_err(|e| format!("chat.rooms: {}: {}", room, e))?;
+        }
+        if self.chat.room_buffer == 0 {
+            return Err("chat.room_buffer must be at least 1".to_string());
+        }
+        if let Some(hash) = &self.geo.sha256 {
+            if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err("geo.sha256 must be 64 hex digits".to_string());
+            }
+        }
+        crate::geo::ManualProvider::new(&self.geo.manual)?;
+        if self.geo.fuzz != FuzzMode::Off && !(self.geo.fuzz_km > 0.0 && self.geo.fuzz_km <= 5000.0)
+        {
+            return Err("geo.fuzz_km must be between 0 and 5000".to_string());
+        }
+        match (self.location.lat, self.location.lon) {
+            (Some(lat), Some(lon)) => {
+                if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+                    return Err("location.lat/lon out of range".to_string());
+                }
+            }
+            (None, None) => {}
+            _ => return Err("location.lat and location.lon must be set together".to_string()),
+        }
+        self.theme.to_theme()?;
+        Ok(())
+    }
+
+    pub fn retention(&self) -> crate::store::RetentionPolicy {
+        crate::store::RetentionPolicy {
+            max_age_days: (self.storage.retention_days > 0).then_some(self.storage.retention_days),
+            max_messages: (self.storage.retention_messages > 0)
+                .then_some(self.storage.retention_messages as usize),
+        }
+    }
+
+    // What `terra-link config show` prints.
+    pub fn to_toml(&self) -> Result<String, String> {
+        let mut out =
+            String::from("# Effective configuration: defaults < files < environment < flags\n");
+        if self.sources.is_empty() {
+            out.push_str("# No config file found; looked in:\n");
+            for path in search_paths() {
+                out.push_str(&format!("#   {}\n", path.display()));
+            }
+        }
+        for path in &self.sources {
+            out.push_str(&format!("# Loaded {}\n", path.display()));
+        }
+        out.push('\n');
+        out.push_str(&toml::to_string_pretty(self).map_err(|e| e.to_string())?);
+        Ok(out)
+    }
+}
+
+impl NetworkConfig {
+    pub fn listen_addrs(&self) -> Result<Vec<Multiaddr>, String> {
+        parse_addrs("network.listen", &self.listen)
+    }
+
+    pub fn dial_addrs(&self) -> Result<Vec<Multiaddr>, String> {
+        parse_addrs("network.dial", &self.dial)
+    }
+
+    pub fn relay_addr(&self) -> Result<Option<Multiaddr>, String> {
+        self.relay
+            .as_deref()
+            .map(|addr| parse_addr("network.relay", addr))
+            .transpose()
+    }
+
+    pub fn bootstrap_addrs(&self) -> Result<Vec<Multiaddr>, String> {
+        parse_addrs("network.bootstrap", &self.bootstrap)
+    }
+
+    pub fn presence_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.presence_interval_secs)
+    }
+}
+
+impl ThemeConfig {
+    pub fn to_theme(&self) -> Result<Theme, String> {
+        let color = |key: &str, value: &str| {
+            crate::profile::parse_color(value)
+                .map(|rgb| Color::Rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+                .ok_or_else(|| format!("theme.{}: expected \"#rrggbb\", got {:?}", key, value))
+        };
+        Ok(Theme {
+            primary: color("primary", &self.primary)?,
+            accent: color("accent", &self.accent)?,
+            highlight: color("highlight", &self.highlight)?,
+            secondary: color("secondary", &self.secondary)?,
+            dim: color("dim", &self.dim)?,
+            text: color("text", &self.text)?,
+            background: color("background", &self.background)?,
+        })
+    }
+}
+
+// System-wide files first ($XDG_CONFIG_DIRS, default /etc/xdg), then the user's own
+// ($XDG_CONFIG_HOME, default ~/.config), so the user's settings win.
+fn search_paths() -> Vec<PathBuf> {
+    let system = std::env::var("XDG_CONFIG_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/etc/xdg".to_string());
+    // XDG lists the most important directory first
+    let mut paths: Vec<PathBuf> = system
+        .split(':')
+        .filter(|dir| !dir.is_empty())
+        .rev()
+        .map(|dir| Path::new(dir).join(APP_DIR).join(FILE_NAME))
+        .collect();
+    if let Some(user) = dirs::config_dir() {
+        paths.push(user.join(APP_DIR).join(FILE_NAME));
+    }
+    paths
+}
+
+// Deep-merges `overlay` into `base`: tables merge key by key, anything else is replaced.
+fn merge(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overlay)) => merge(base, overlay),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+fn env_number(name: &str) -> Result<Option<u64>, String> {
+    match std::env::var(name) {
+        Ok(value) => value
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("{} must be a whole number, got {:?}", name, value)),
+        Err(_) => Ok(None),
+    }
+}
+
+fn parse_addrs(key: &str, values: &[String]) -> Result<Vec<Multiaddr>, String> {
+    values.iter().map(|value| parse_addr(key, value)).collect()
+}
+
+fn parse_addr(key: &str, value: &str) -> Result<Multiaddr, String> {
+    value
+        .parse()
+        .map_err(|e| format!("{}: invalid multiaddr {:?}: {}", key, value, e))
+}
+
+fn hex(color: Color) -> String {
+    match color {
+        Color::Rgb(r, g, b) => format!("#{:02x}{:02x}{:02x}", r, g, b),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn write(dir: &Path, text: &str) {
+        std::fs::create_dir_all(dir.join(APP_DIR)).unwrap();
+        std::fs::write(dir.join(APP_DIR).join(FILE_NAME), text).unwrap();
+    }
+
+    // The only test touching these variables, so it can't race another test reading them
+    #[test]
+    fn each_layer_overrides_the_one_before() {
+        let root = tempfile::tempdir().unwrap();
+        let (low, high, user) = (
+            root.path().join("low"),
+            root.path().join("high"),
+            root.path().join("user"),
+        );
+        std::env::set_var(
+            "XDG_CONFIG_DIRS",
+            format!("{}:{}", high.display(), low.display()),
+        );
+        std::env::set_var("XDG_CONFIG_HOME", &user);
+        std::env::remove_var("TERRA_LINK_GEO_DB");
+        let db_path = || Config::load(None).unwrap().geo.db_path;
+
+        write(
+            &low,
+            "[geo]\ndb_path = \"/low\"\n[chat]\ntime_format = \"%H\"\n",
+        );
+        assert_eq!(db_path(), Path::new("/low"));
+        write(&high, "[geo]\ndb_path = \"/high\"\n");
+        assert_eq!(db_path(), Path::new("/high"));
+        write(&user, "[geo]\ndb_path = \"/user\"\n");
+        assert_eq!(db_path(), Path::new("/user"));
+
+        std::env::set_var("TERRA_LINK_GEO_DB", "/env");
+        let mut config = Config::load(None).unwrap();
+        std::env::remove_var("TERRA_LINK_GEO_DB");
+        assert_eq!(config.geo.db_path, Path::new("/env"));
+        // Tables merge key by key, so settings only a lower layer sets survive
+        assert_eq!(config.chat.time_format, "%H");
+
+        config.apply_cli(&Cli::parse_from(["terra-link", "--geo-db", "/cli"]));
+        assert_eq!(config.geo.db_path, Path::new("/cli"));
+    }
+}
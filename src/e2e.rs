@@ -0,0 +1,402 @@
+use crate::proto::messages::{DirectMessage, SealedDirectMessage, SealedRoomMessage};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+use curve25519_dalek::edwards::CompressedEdwardsY;
+use hkdf::Hkdf;
+use libp2p::{identity, PeerId};
+use prost::Message;
+use rand::RngCore;
+use sha2::{Digest, Sha256, Sha512};
+use std::collections::{HashSet, VecDeque};
+use std::time::{SystemTime, UNIX_EPOCH};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+const KDF_INFO: &[u8] = b"terra-link/dm/v1";
+const ROOM_KDF_SALT: &[u8] = b"terra-link/room/v1/";
+
+// Messages stamped further than this from our clock are rejected, which bounds the replay cache.
+const MAX_CLOCK_SKEW_MS: u64 = 5 * 60 * 1000;
+
+// Seals and opens direct messages end-to-end.
+//
+// Both ends' X25519 keys are derived from their ed25519 libp2p identities, so the recipient's
+// key comes straight out of its PeerId and nothing extra has to be published. Each message uses
+// a fresh ephemeral key (forward secrecy for the sender side) mixed with a static-static DH that
+// only the real sender could compute, which authenticates them.
+pub struct DmCrypto {
+    local_peer_id: PeerId,
+    static_secret: StaticSecret,
+    static_public: PublicKey,
+    // Ephemeral keys of recently accepted messages, oldest first, for replay rejection
+    seen: HashSet<[u8; 32]>,
+    seen_order: VecDeque<(u64, [u8; 32])>,
+}
+
+impl DmCrypto {
+    pub fn new(keypair: &identity::Keypair) -> Result<Self, String> {
+        let ed = keypair
+            .clone()
+            .try_into_ed25519()
+            .map_err(|_| "Direct message encryption requires an ed25519 identity".to_string())?;
+
+        // Same scalar derivation ed25519 itself uses: the clamped low half of SHA-512(seed)
+        let hash = Sha512::digest(ed.secret().as_ref());
+        let mut scalar = [0u8; 32];
+        scalar.copy_from_slice(&hash[..32]);
+        let static_secret = StaticSecret::from(scalar);
+
+        Ok(Self {
+            local_peer_id: keypair.public().to_peer_id(),
+            static_public: PublicKey::from(&static_secret),
+            static_secret,
+            seen: HashSet::new(),
+            seen_order: VecDeque::new(),
+        })
+    }
+
+    pub fn seal(
+        &self,
+        recipient: &PeerId,
+        dm: &DirectMessage,
+    ) -> Result<SealedDirectMessage, String> {
+        let recipient_public = peer_x25519_key(recipient)?;
+
+        let mut eph_bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut eph_bytes);
+        let ephemeral = StaticSecret::from(eph_bytes);
+        let ephemeral_public = PublicKey::from(&ephemeral);
+
+        let dh_ephemeral = ephemeral.diffie_hellman(&recipient_public);
+        let dh_static = self.static_secret.diffie_hellman(&recipient_public);
+        if !dh_ephemeral.was_contributory() || !dh_static.was_contributory() {
+            return Err(format!("Invalid encryption key for peer {}", recipient));
+        }
+
+        let key = derive_key(
+            dh_ephemeral.as_bytes(),
+            dh_static.as_bytes(),
+            &ephemeral_public,
+            &self.static_public,
+            &recipient_public,
+        );
+
+        let mut nonce = [0u8; 12];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let aad = associated_data(&self.local_peer_id, recipient, dm.timestamp);
+        let ciphertext = ChaCha20Poly1305::new((&key).into())
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &dm.encode_to_vec(),
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| "Failed to encrypt direct message".to_string())?;
+
+        Ok(SealedDirectMessage {
+            ephemeral_key: ephemeral_public.as_bytes().to_vec(),
+            nonce: nonce.to_vec(),
+            ciphertext,
+            timestamp: dm.timestamp,
+        })
+    }
+
+    // `sender` must be the transport-authenticated remote, never a claimed id from the payload.
+    pub fn open(
+        &mut self,
+        sender: &PeerId,
+        sealed: &SealedDirectMessage,
+    ) -> Result<DirectMessage, String> {
+        let now = now_millis();
+        if sealed.timestamp.abs_diff(now) > MAX_CLOCK_SKEW_MS {
+            return Err(format!(
+                "Rejected direct message from {}: timestamp outside the accepted window",
+                sender
+            ));
+        }
+
+        let ephemeral_bytes: [u8; 32] = sealed
+            .ephemeral_key
+            .as_slice()
+            .try_into()
+            .map_err(|_| format!("Malformed direct message from {}", sender))?;
+        if sealed.nonce.len() != 12 {
+            return Err(format!("Malformed direct message from {}", sender));
+        }
+        if self.seen.contains(&ephemeral_bytes) {
+            return Err(format!("Rejected replayed direct message from {}", sender));
+        }
+
+        let ephemeral_public = PublicKey::from(ephemeral_bytes);
+        let sender_public = peer_x25519_key(sender)?;
+
+        let dh_ephemeral = self.static_secret.diffie_hellman(&ephemeral_public);
+        let dh_static = self.static_secret.diffie_hellman(&sender_public);
+        if !dh_ephemeral.was_contributory() || !dh_static.was_contributory() {
+            return Err(format!("Invalid encryption key from peer {}", sender));
+        }
+
+        let key = derive_key(
+            dh_ephemeral.as_bytes(),
+            dh_static.as_bytes(),
+            &ephemeral_public,
+            &sender_public,
+            &self.static_public,
+        );
+
+        let aad = associated_data(sender, &self.local_peer_id, sealed.timestamp);
+        let plaintext = ChaCha20Poly1305::new((&key).into())
+            .decrypt(
+                Nonce::from_slice(&sealed.nonce),
+                Payload {
+                    msg: &sealed.ciphertext,
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| format!("Failed to decrypt direct message from {}", sender))?;
+
+        let dm = DirectMessage::decode(plaintext.as_slice())
+            .map_err(|e| format!("Corrupt direct message from {}: {}", sender, e))?;
+        if dm.receiver_id != self.local_peer_id.to_string() || dm.timestamp != sealed.timestamp {
+            return Err(format!(
+                "Direct message from {} was not addressed to us",
+                sender
+            ));
+        }
+
+        self.remember(now, ephemeral_bytes);
+        Ok(dm)
+    }
+
+    fn remember(&mut self, now: u64, ephemeral: [u8; 32]) {
+        // A replay of anything accepted more than two skew windows ago already fails the timestamp check
+        while let Some((seen_at, key)) = self.seen_order.front().copied() {
+            if now.saturating_sub(seen_at) <= 2 * MAX_CLOCK_SKEW_MS {
+                break;
+            }
+            self.seen_order.pop_front();
+            self.seen.remove(&key);
+        }
+        self.seen.insert(ephemeral);
+        self.seen_order.push_back((now, ephemeral));
+    }
+}
+
+// Symmetric key for a passphrase-protected room, shared by everyone who knows the passphrase.
+#[derive(Clone)]
+pub struct RoomKey {
+    key: [u8; 32],
+}
+
+impl std::fmt::Debug for RoomKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("RoomKey(..)") // never log key material
+    }
+}
+
+impl RoomKey {
+    // Argon2id stretches the passphrase so captured traffic can't be cheaply brute forced.
+    // The room name salts it, so the same passphrase yields unrelated keys for different rooms.
+    pub fn derive(room_name: &str, passphrase: &str) -> Result<Self, String> {
+        let mut salt = ROOM_KDF_SALT.to_vec();
+        salt.extend_from_slice(room_name.as_bytes());
+
+        let mut key = [0u8; 32];
+        argon2::Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+            .map_err(|e| format!("Failed to derive room key: {}", e))?;
+        Ok(Self { key })
+    }
+
+    // Gossipsub topic for the room. It is a hash of the key, so the topic reveals neither the
+    // room name nor the passphrase, and only key holders know which topic to subscribe to.
+    pub fn topic(&self) -> String {
+        let digest = Sha256::new()
+            .chain_update(b"terra-link/room-topic/v1")
+            .chain_update(self.key)
+            .finalize();
+        let hex: String = digest[..16].iter().map(|b| format!("{:02x}", b)).collect();
+        format!("/private/{}", hex)
+    }
+
+    pub fn seal(&self, plaintext: &[u8]) -> Result<SealedRoomMessage, String> {
+        let mut nonce = [0u8; 12];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let ciphertext = ChaCha20Poly1305::new((&self.key).into())
+            .encrypt(Nonce::from_slice(&nonce), plaintext)
+            .map_err(|_| "Failed to encrypt room message".to_string())?;
+        Ok(SealedRoomMessage {
+            nonce: nonce.to_vec(),
+            ciphertext,
+            message_id: String::new(), // set by the publisher, it's outside the sealed part
+        })
+    }
+
+    pub fn open(&self, sealed: &SealedRoomMessage) -> Result<Vec<u8>, String> {
+        if sealed.nonce.len() != 12 {
+            return Err("Malformed room message".to_string());
+        }
+        ChaCha20Poly1305::new((&self.key).into())
+            .decrypt(
+                Nonce::from_slice(&sealed.nonce),
+                sealed.ciphertext.as_slice(),
+            )
+            .map_err(|_| "Failed to decrypt room message".to_string())
+    }
+}
+
+// Recover a peer's X25519 key from the ed25519 public key inlined in its PeerId.
+fn peer_x25519_key(peer: &PeerId) -> Result<PublicKey, String> {
+    let ed = crate::signing::peer_public_key(peer)?
+        .try_into_ed25519()
+        .map_err(|_| format!("Peer {} does not use an ed25519 identity", peer))?;
+
+    let montgomery = CompressedEdwardsY(ed.to_bytes())
+        .decompress()
+        .ok_or_else(|| format!("Peer {} has an invalid public key", peer))?
+        .to_montgomery();
+    Ok(PublicKey::from(montgomery.to_bytes()))
+}
+
+fn derive_key(
+    dh_ephemeral: &[u8; 32],
+    dh_static: &[u8; 32],
+    ephemeral: &PublicKey,
+    sender: &PublicKey,
+    recipient: &PublicKey,
+) -> [u8; 32] {
+    let mut ikm = [0u8; 64];
+    ikm[..32].copy_from_slice(dh_ephemeral);
+    ikm[32..].copy_from_slice(dh_static);
+
+    let mut salt = Vec::with_capacity(96);
+    salt.extend_from_slice(ephemeral.as_bytes());
+    salt.extend_from_slice(sender.as_bytes());
+    salt.extend_from_slice(recipient.as_bytes());
+
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(&salt), &ikm)
+        .expand(KDF_INFO, &mut key)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    key
+}
+
+fn associated_data(sender: &PeerId, recipient: &PeerId, timestamp: u64) -> Vec<u8> {
+    let mut aad = sender.to_bytes();
+    aad.extend_from_slice(&recipient.to_bytes());
+    aad.extend_from_slice(&timestamp.to_be_bytes());
+    aad
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node() -> (PeerId, DmCrypto) {
+        let keypair = identity::Keypair::generate_ed25519();
+        (
+            keypair.public().to_peer_id(),
+            DmCrypto::new(&keypair).unwrap(),
+        )
+    }
+
+    fn dm_to(recipient: &PeerId, timestamp: u64) -> DirectMessage {
+        DirectMessage {
+            sender_id: "alice".to_string(),
+            receiver_id: recipient.to_string(),
+            text: "hello".to_string(),
+            timestamp,
+            message_id: "m1".to_string(),
+        }
+    }
+
+    #[test]
+    fn seal_open_round_trip() {
+        let (alice, alice_crypto) = node();
+        let (bob, mut bob_crypto) = node();
+        let dm = dm_to(&bob, now_millis());
+        let sealed = alice_crypto.seal(&bob, &dm).unwrap();
+        assert_eq!(bob_crypto.open(&alice, &sealed).unwrap(), dm);
+    }
+
+    #[test]
+    fn wrong_recipient_or_sender_is_rejected() {
+        let (alice, alice_crypto) = node();
+        let (bob, mut bob_crypto) = node();
+        let (carol, mut carol_crypto) = node();
+        let sealed = alice_crypto.seal(&bob, &dm_to(&bob, now_millis())).unwrap();
+
+        assert!(carol_crypto.open(&alice, &sealed).is_err());
+        // Bob can't be talked into believing Carol wrote it
+        assert!(bob_crypto.open(&carol, &sealed).is_err());
+    }
+
+    #[test]
+    fn replay_is_rejected() {
+        let (alice, alice_crypto) = node();
+        let (bob, mut bob_crypto) = node();
+        let sealed = alice_crypto.seal(&bob, &dm_to(&bob, now_millis())).unwrap();
+        assert!(bob_crypto.open(&alice, &sealed).is_ok());
+        assert!(bob_crypto.open(&alice, &sealed).is_err());
+    }
+
+    #[test]
+    fn clock_skew_is_rejected() {
+        let (alice, alice_crypto) = node();
+        let (bob, mut bob_crypto) = node();
+        for timestamp in [
+            now_millis() - 2 * MAX_CLOCK_SKEW_MS,
+            now_millis() + 2 * MAX_CLOCK_SKEW_MS,
+        ] {
+            let sealed = alice_crypto.seal(&bob, &dm_to(&bob, timestamp)).unwrap();
+            assert!(bob_crypto.open(&alice, &sealed).is_err());
+        }
+    }
+
+    #[test]
+    fn room_key_is_deterministic_per_room_and_passphrase() {
+        let key = RoomKey::derive("team", "hunter2").unwrap();
+        let topic = key.topic();
+        assert_eq!(RoomKey::derive("team", "hunter2").unwrap().topic(), topic);
+        assert_ne!(RoomKey::derive("team", "hunter3").unwrap().topic(), topic);
+        assert_ne!(RoomKey::derive("crew", "hunter2").unwrap().topic(), topic);
+
+        let hash = topic.strip_prefix("/private/").unwrap();
+        assert_eq!(hash.len(), 32);
+        assert!(hash.chars().all(|c| c.is_ascii_hexdigit()));
+        assert!(!topic.contains("team") && !topic.contains("hunter2"));
+    }
+
+    #[test]
+    fn room_seal_open_round_trip() {
+        let key = RoomKey::derive("team", "hunter2").unwrap();
+        let sealed = key.seal(b"hello").unwrap();
+        assert_ne!(sealed.ciphertext, b"hello");
+        assert_eq!(key.open(&sealed).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn room_wrong_key_or_room_is_rejected() {
+        let sealed = RoomKey::derive("team", "hunter2")
+            .unwrap()
+            .seal(b"hello")
+            .unwrap();
+        let wrong_passphrase = RoomKey::derive("team", "letmein").unwrap();
+        let wrong_room = RoomKey::derive("crew", "hunter2").unwrap();
+        assert!(wrong_passphrase.open(&sealed).is_err());
+        assert!(wrong_room.open(&sealed).is_err());
+
+        let mut tampered = sealed.clone();
+        tampered.ciphertext[0] ^= 1;
+        let key = RoomKey::derive("team", "hunter2").unwrap();
+        assert!(key.open(&tampered).is_err());
+    }
+}
@@ -0,0 +1,403 @@
+use super::{GeoProvider, Location};
+use std::net::IpAddr;
+
+// Works with no database at all. Addresses in one of the large single-country blocks in
+// `IPV4_COUNTRIES` are placed at that country's centre; anything else falls back to the
+// continent of the regional registry its block was handed to. Either is enough for an
+// approximate marker.
+pub struct BuiltinRanges;
+
+impl GeoProvider for BuiltinRanges {
+    fn locate(&self, ip: IpAddr) -> Option<Location> {
+        let mut location = range_country(ip)
+            .and_then(country)
+            .or_else(|| continent(registry_continent(ip)?))?;
+        location.label.push_str(" (approx.)");
+        Some(location)
+    }
+}
+
+// Rough geographic centre of a country, by ISO 3166-1 alpha-2 code.
+pub fn country(code: &str) -> Option<Location> {
+    let code = code.to_ascii_uppercase();
+    COUNTRIES
+        .iter()
+        .find(|(iso, ..)| *iso == code)
+        .map(|&(_, name, lat, lon)| Location {
+            lat,
+            lon,
+            label: name.to_string(),
+        })
+}
+
+// Continent codes as MaxMind uses them: AF, AN, AS, EU, NA, OC, SA.
+pub fn continent(code: &str) -> Option<Location> {
+    let code = code.to_ascii_uppercase();
+    CONTINENTS
+        .iter()
+        .find(|(iso, ..)| *iso == code)
+        .map(|&(_, name, lat, lon)| Location {
+            lat,
+            lon,
+            label: name.to_string(),
+        })
+}
+
+// The country of the most specific `IPV4_COUNTRIES` block containing `ip`.
+fn range_country(ip: IpAddr) -> Option<&'static str> {
+    let IpAddr::V4(v4) = ip else {
+        return None;
+    };
+    let ip = u32::from(v4);
+    IPV4_COUNTRIES
+        .iter()
+        .filter(|&&(start, len, _)| ip >> (32 - len) == u32::from_be_bytes(start) >> (32 - len))
+        .max_by_key(|&&(_, len, _)| len)
+        .map(|&(.., code)| code)
+}
+
+// Which continent's registry (ARIN, RIPE NCC, APNIC, LACNIC, AFRINIC) an address was
+// allocated by, from IANA's top-level delegations. Legacy and mixed blocks are skipped.
+fn registry_continent(ip: IpAddr) -> Option<&'static str> {
+    match ip {
+        IpAddr::V4(v4) => match v4.octets()[0] {
+            1
+            | 14
+            | 27
+            | 36
+            | 39
+            | 42
+            | 43
+            | 49
+            | 58..=61
+            | 101
+            | 103
+            | 106
+            | 110..=126
+            | 133
+            | 150
+            | 153
+            | 163
+            | 171
+            | 175
+            | 180
+            | 182
+            | 183
+            | 202
+            | 203
+            | 210
+            | 211
+            | 218..=223 => Some("AS"),
+            2
+            | 5
+            | 31
+            | 37
+            | 46
+            | 62
+            | 77..=95
+            | 109
+            | 141
+            | 145
+            | 151
+            | 176
+            | 178
+            | 185
+            | 188
+            | 193..=195
+            | 212
+            | 213
+            | 217 => Some("EU"),
+            23
+            | 24
+            | 50
+            | 63..=76
+            | 96..=100
+            | 104
+            | 107
+            | 108
+            | 173
+            | 174
+            | 184
+            | 199
+            | 204..=209
+            | 216 => Some("NA"),
+            177 | 179 | 181 | 186 | 187 | 189..=191 | 200 | 201 => Some("SA"),
+            41 | 102 | 105 | 154 | 196 | 197 => Some("AF"),
+            _ => None,
+        },
+        IpAddr::V6(v6) => match v6.segments()[0] >> 4 {
+            0x240 => Some("AS"),
+            0x260 => Some("NA"),
+            0x280 => Some("SA"),
+            0x2a0 => Some("EU"),
+            0x2c0 => Some("AF"),
+            _ => None,
+        },
+    }
+}
+
+// Large IPv4 blocks held by a single organisation or national ISP, so almost every address
+// in them is in one country: (network, prefix length, ISO code). Far from complete; it only
+// has to beat a continent for the blocks peers most often come from.
+const IPV4_COUNTRIES: &[([u8; 4], u8, &str)] = &[
+    // Legacy /8s held by US companies and agencies
+    ([3, 0, 0, 0], 8, "US"),
+    ([4, 0, 0, 0], 8, "US"),
+    ([6, 0, 0, 0], 8, "US"),
+    ([7, 0, 0, 0], 8, "US"),
+    ([8, 0, 0, 0], 8, "US"),
+    ([9, 0, 0, 0], 8, "US"),
+    ([11, 0, 0, 0], 8, "US"),
+    ([12, 0, 0, 0], 8, "US"),
+    ([13, 0, 0, 0], 8, "US"),
+    ([15, 0, 0, 0], 8, "US"),
+    ([16, 0, 0, 0], 8, "US"),
+    ([17, 0, 0, 0], 8, "US"),
+    ([18, 0, 0, 0], 8, "US"),
+    ([19, 0, 0, 0], 8, "US"),
+    ([20, 0, 0, 0], 8, "US"),
+    ([21, 0, 0, 0], 8, "US"),
+    ([22, 0, 0, 0], 8, "US"),
+    ([26, 0, 0, 0], 8, "US"),
+    ([28, 0, 0, 0], 8, "US"),
+    ([29, 0, 0, 0], 8, "US"),
+    ([30, 0, 0, 0], 8, "US"),
+    ([32, 0, 0, 0], 8, "US"),
+    ([33, 0, 0, 0], 8, "US"),
+    ([34, 0, 0, 0], 8, "US"),
+    ([35, 0, 0, 0], 8, "US"),
+    ([38, 0, 0, 0], 8, "US"),
+    ([40, 0, 0, 0], 8, "US"),
+    ([48, 0, 0, 0], 8, "US"),
+    ([52, 0, 0, 0], 8, "US"),
+    ([54, 0, 0, 0], 8, "US"),
+    ([55, 0, 0, 0], 8, "US"),
+    ([56, 0, 0, 0], 8, "US"),
+    ([73, 0, 0, 0], 8, "US"),
+    ([214, 0, 0, 0], 8, "US"),
+    ([215, 0, 0, 0], 8, "US"),
+    // Europe
+    ([25, 0, 0, 0], 8, "GB"),
+    ([81, 128, 0, 0], 11, "GB"),
+    ([86, 128, 0, 0], 10, "GB"),
+    ([53, 0, 0, 0], 8, "DE"),
+    ([79, 192, 0, 0], 10, "DE"),
+    ([80, 128, 0, 0], 11, "DE"),
+    ([84, 128, 0, 0], 10, "DE"),
+    ([87, 128, 0, 0], 10, "DE"),
+    ([91, 0, 0, 0], 10, "DE"),
+    ([93, 192, 0, 0], 10, "DE"),
+    ([217, 224, 0, 0], 11, "DE"),
+    ([2, 0, 0, 0], 12, "FR"),
+    ([88, 160, 0, 0], 11, "FR"),
+    ([90, 0, 0, 0], 9, "FR"),
+    ([92, 128, 0, 0], 10, "FR"),
+    ([83, 32, 0, 0], 11, "ES"),
+    ([88, 0, 0, 0], 11, "ES"),
+    ([151, 0, 0, 0], 10, "IT"),
+    ([145, 0, 0, 0], 8, "NL"),
+    ([83, 0, 0, 0], 11, "PL"),
+    ([78, 160, 0, 0], 11, "TR"),
+    ([88, 224, 0, 0], 11, "TR"),
+    // Asia and Oceania
+    ([39, 128, 0, 0], 10, "CN"),
+    ([111, 0, 0, 0], 10, "CN"),
+    ([117, 128, 0, 0], 10, "CN"),
+    ([120, 192, 0, 0], 10, "CN"),
+    ([183, 192, 0, 0], 10, "CN"),
+    ([223, 64, 0, 0], 11, "CN"),
+    ([126, 0, 0, 0], 8, "JP"),
+    ([133, 0, 0, 0], 8, "JP"),
+    ([121, 128, 0, 0], 10, "KR"),
+    ([175, 192, 0, 0], 10, "KR"),
+    ([36, 224, 0, 0], 12, "TW"),
+    ([111, 240, 0, 0], 12, "TW"),
+    ([117, 192, 0, 0], 10, "IN"),
+    ([113, 160, 0, 0], 11, "VN"),
+    ([36, 64, 0, 0], 11, "ID"),
+    ([101, 160, 0, 0], 11, "AU"),
+    // South America
+    ([177, 0, 0, 0], 8, "BR"),
+    ([179, 0, 0, 0], 8, "BR"),
+    ([191, 0, 0, 0], 8, "BR"),
+];
+
+const CONTINENTS: &[(&str, &str, f64, f64)] = &[
+    ("AF", "Africa", 5.0, 20.0),
+    ("AN", "Antarctica", -80.0, 0.0),
+    ("AS", "Asia", 34.0, 100.0),
+    ("EU", "Europe", 50.0, 15.0),
+    ("NA", "North America", 45.0, -100.0),
+    ("OC", "Oceania", -25.0, 135.0),
+    ("SA", "South America", -15.0, -60.0),
+];
+
+const COUNTRIES: &[(&str, &str, f64, f64)] = &[
+    // Americas
+    ("US", "United States", 39.8, -98.6),
+    ("CA", "Canada", 56.1, -106.3),
+    ("MX", "Mexico", 23.6, -102.6),
+    ("GT", "Guatemala", 15.8, -90.2),
+    ("CR", "Costa Rica", 9.7, -83.8),
+    ("PA", "Panama", 8.5, -80.8),
+    ("CU", "Cuba", 21.5, -77.8),
+    ("JM", "Jamaica", 18.1, -77.3),
+    ("DO", "Dominican Republic", 18.7, -70.2),
+    ("PR", "Puerto Rico", 18.2, -66.6),
+    ("CO", "Colombia", 4.6, -74.3),
+    ("VE", "Venezuela", 6.4, -66.6),
+    ("EC", "Ecuador", -1.8, -78.2),
+    ("PE", "Peru", -9.2, -75.0),
+    ("BO", "Bolivia", -16.3, -63.6),
+    ("BR", "Brazil", -14.2, -51.9),
+    ("PY", "Paraguay", -23.4, -58.4),
+    ("UY", "Uruguay", -32.5, -55.8),
+    ("AR", "Argentina", -38.4, -63.6),
+    ("CL", "Chile", -35.7, -71.5),
+    // Europe
+    ("IS", "Iceland", 65.0, -19.0),
+    ("IE", "Ireland", 53.4, -8.2),
+    ("GB", "United Kingdom", 55.4, -3.4),
+    ("PT", "Portugal", 39.4, -8.2),
+    ("ES", "Spain", 40.5, -3.7),
+    ("FR", "France", 46.2, 2.2),
+    ("BE", "Belgium", 50.5, 4.5),
+    ("NL", "Netherlands", 52.1, 5.3),
+    ("LU", "Luxembourg", 49.8, 6.1),
+    ("DE", "Germany", 51.2, 10.5),
+    ("CH", "Switzerland", 46.8, 8.2),
+    ("AT", "Austria", 47.5, 14.6),
+    ("IT", "Italy", 41.9, 12.6),
+    ("MT", "Malta", 35.9, 14.4),
+    ("DK", "Denmark", 56.3, 9.5),
+    ("NO", "Norway", 60.5, 8.5),
+    ("SE", "Sweden", 60.1, 18.6),
+    ("FI", "Finland", 61.9, 25.7),
+    ("EE", "Estonia", 58.6, 25.0),
+    ("LV", "Latvia", 56.9, 24.6),
+    ("LT", "Lithuania", 55.2, 23.9),
+    ("PL", "Poland", 51.9, 19.1),
+    ("CZ", "Czechia", 49.8, 15.5),
+    ("SK", "Slovakia", 48.7, 19.7),
+    ("HU", "Hungary", 47.2, 19.5),
+    ("SI", "Slovenia", 46.2, 15.0),
+    ("HR", "Croatia", 45.1, 15.2),
+    ("BA", "Bosnia and Herzegovina", 43.9, 17.7),
+    ("RS", "Serbia", 44.0, 21.0),
+    ("RO", "Romania", 45.9, 25.0),
+    ("BG", "Bulgaria", 42.7, 25.5),
+    ("GR", "Greece", 39.1, 21.8),
+    ("CY", "Cyprus", 35.1, 33.4),
+    ("MD", "Moldova", 47.4, 28.4),
+    ("UA", "Ukraine", 48.4, 31.2),
+    ("BY", "Belarus", 53.7, 28.0),
+    ("RU", "Russia", 61.5, 105.3),
+    // Middle East and Central Asia
+    ("TR", "Turkey", 39.0, 35.2),
+    ("GE", "Georgia", 42.3, 43.4),
+    ("AM", "Armenia", 40.1, 45.0),
+    ("AZ", "Azerbaijan", 40.1, 47.6),
+    ("KZ", "Kazakhstan", 48.0, 66.9),
+    ("UZ", "Uzbekistan", 41.4, 64.6),
+    ("IL", "Israel", 31.0, 34.9),
+    ("PS", "Palestine", 31.9, 35.2),
+    ("LB", "Lebanon", 33.9, 35.9),
+    ("SY", "Syria", 34.8, 39.0),
+    ("JO", "Jordan", 30.6, 36.2),
+    ("IQ", "Iraq", 33.2, 43.7),
+    ("IR", "Iran", 32.4, 53.7),
+    ("SA", "Saudi Arabia", 23.9, 45.1),
+    ("KW", "Kuwait", 29.3, 47.5),
+    ("BH", "Bahrain", 26.0, 50.6),
+    ("QA", "Qatar", 25.4, 51.2),
+    ("AE", "United Arab Emirates", 23.4, 53.8),
+    ("OM", "Oman", 21.5, 55.9),
+    ("YE", "Yemen", 15.6, 48.5),
+    // Africa
+    ("EG", "Egypt", 26.8, 30.8),
+    ("LY", "Libya", 26.3, 17.2),
+    ("TN", "Tunisia", 33.9, 9.5),
+    ("DZ", "Algeria", 28.0, 1.7),
+    ("MA", "Morocco", 31.8, -7.1),
+    ("SN", "Senegal", 14.5, -14.5),
+    ("CI", "Côte d'Ivoire", 7.5, -5.5),
+    ("GH", "Ghana", 7.9, -1.0),
+    ("NG", "Nigeria", 9.1, 8.7),
+    ("CM", "Cameroon", 7.4, 12.4),
+    ("ET", "Ethiopia", 9.1, 40.5),
+    ("KE", "Kenya", 0.0, 37.9),
+    ("UG", "Uganda", 1.4, 32.3),
+    ("RW", "Rwanda", -1.9, 29.9),
+    ("TZ", "Tanzania", -6.4, 34.9),
+    ("CD", "DR Congo", -4.0, 21.8),
+    ("AO", "Angola", -11.2, 17.9),
+    ("ZM", "Zambia", -13.1, 27.8),
+    ("ZW", "Zimbabwe", -19.0, 29.2),
+    ("MZ", "Mozambique", -18.7, 35.5),
+    ("MG", "Madagascar", -18.8, 46.9),
+    ("NA", "Namibia", -23.0, 18.5),
+    ("BW", "Botswana", -22.3, 24.7),
+    ("ZA", "South Africa", -30.6, 22.9),
+    // South and East Asia
+    ("PK", "Pakistan", 30.4, 69.3),
+    ("IN", "India", 20.6, 79.0),
+    ("NP", "Nepal", 28.4, 84.1),
+    ("BD", "Bangladesh", 23.7, 90.4),
+    ("LK", "Sri Lanka", 7.9, 80.8),
+    ("CN", "China", 35.9, 104.2),
+    ("MN", "Mongolia", 46.9, 103.8),
+    ("HK", "Hong Kong", 22.3, 114.2),
+    ("TW", "Taiwan", 23.7, 121.0),
+    ("KR", "South Korea", 35.9, 127.8),
+    ("JP", "Japan", 36.2, 138.3),
+    ("MM", "Myanmar", 21.9, 96.0),
+    ("TH", "Thailand", 15.9, 101.0),
+    ("LA", "Laos", 19.9, 102.5),
+    ("KH", "Cambodia", 12.6, 105.0),
+    ("VN", "Vietnam", 14.1, 108.3),
+    ("MY", "Malaysia", 4.2, 102.0),
+    ("SG", "Singapore", 1.35, 103.8),
+    ("ID", "Indonesia", -0.8, 113.9),
+    ("PH", "Philippines", 12.9, 121.8),
+    // Oceania
+    ("PG", "Papua New Guinea", -6.3, 144.0),
+    ("AU", "Australia", -25.3, 133.8),
+    ("NZ", "New Zealand", -40.9, 174.9),
+    ("FJ", "Fiji", -17.7, 178.1),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn label(ip: &str) -> Option<String> {
+        BuiltinRanges
+            .locate(ip.parse().unwrap())
+            .map(|location| location.label)
+    }
+
+    #[test]
+    fn places_listed_blocks_in_their_country() {
+        assert_eq!(
+            label("17.1.2.3").as_deref(),
+            Some("United States (approx.)")
+        );
+        assert_eq!(label("84.130.0.1").as_deref(), Some("Germany (approx.)"));
+        assert_eq!(label("126.255.255.255").as_deref(), Some("Japan (approx.)"));
+    }
+
+    #[test]
+    fn prefers_the_most_specific_block() {
+        assert_eq!(range_country("111.250.0.1".parse().unwrap()), Some("TW"));
+        assert_eq!(range_country("111.10.0.1".parse().unwrap()), Some("CN"));
+        // Just outside 86.128.0.0/10
+        assert_eq!(range_country("86.127.255.255".parse().unwrap()), None);
+    }
+
+    #[test]
+    fn falls_back_to_the_registry_continent() {
+        assert_eq!(label("185.1.2.3").as_deref(), Some("Europe (approx.)"));
+        assert_eq!(label("2a00::1").as_deref(), Some("Europe (approx.)"));
+        assert_eq!(label("240.0.0.1"), None);
+    }
+}
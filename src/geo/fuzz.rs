@@ -0,0 +1,179 @@
+use crate::config::FuzzMode;
+use libp2p::PeerId;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+const KM_PER_DEGREE: f64 = 111.32;
+
+// Blurs GeoIP coordinates before they're drawn. The offset comes from a hash of the peer
+// and a salt that never leaves this machine, so a peer's marker stays put across
+// reconnects (nothing to average away), yet other users see it somewhere else.
+pub struct Fuzzer {
+    mode: FuzzMode,
+    km: f64, // offset radius, or grid cell size
+    salt: [u8; 32],
+}
+
+impl Fuzzer {
+    pub fn new(mode: FuzzMode, km: f64, salt: [u8; 32]) -> Self {
+        Self { mode, km, salt }
+    }
+
+    pub fn apply(&self, peer: &PeerId, lat: f64, lon: f64) -> (f64, f64) {
+        match self.mode {
+            FuzzMode::Off => (lat, lon),
+            FuzzMode::Offset => {
+                let (a, b) = self.unit_pair(peer);
+                // sqrt keeps the offsets evenly spread over the disc, not bunched at its centre
+                let distance = self.km * a.sqrt();
+                let bearing = b * std::f64::consts::TAU;
+                let dlat = distance * bearing.cos() / KM_PER_DEGREE;
+                let dlon =
+                    distance * bearing.sin() / (KM_PER_DEGREE * lat.to_radians().cos().max(0.01));
+                ((lat + dlat).clamp(-90.0, 90.0), wrap_longitude(lon + dlon))
+            }
+            // Everyone in the same cell lands on its centre
+            FuzzMode::Grid => {
+                let step = self.km / KM_PER_DEGREE;
+                let snap = |value: f64| ((value / step).floor() + 0.5) * step;
+                (snap(lat).clamp(-90.0, 90.0), wrap_longitude(snap(lon)))
+            }
+        }
+    }
+
+    // A stable stand-in location for peers with no real one (e.g. loopback test nodes).
+    pub fn anywhere(&self, peer: &PeerId) -> (f64, f64) {
+        let (a, b) = self.unit_pair(peer);
+        (a * 160.0 - 80.0, b * 360.0 - 180.0)
+    }
+
+    // Two numbers in [0, 1) that only this peer, under this salt, maps to.
+    fn unit_pair(&self, peer: &PeerId) -> (f64, f64) {
+        let digest = Sha256::new()
+            .chain_update(self.salt)
+            .chain_update(peer.to_bytes())
+            .finalize();
+        let unit = |bytes: &[u8]| {
+            u64::from_be_bytes(bytes.try_into().unwrap_or_default()) as f64 / 2f64.powi(64)
+        };
+        (unit(&digest[..8]), unit(&digest[8..16]))
+    }
+}
+
+fn wrap_longitude(lon: f64) -> f64 {
+    (lon + 180.0).rem_euclid(360.0) - 180.0
+}
+
+// The salt is generated once and kept with the node's other private state.
+pub fn load_or_create_salt(path: &Path) -> io::Result<[u8; 32]> {
+    match fs::read(path) {
+        Ok(bytes) => bytes.try_into().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Corrupt fuzz salt {}", path.display()),
+            )
+        }),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let mut salt = [0u8; 32];
+            rand::thread_rng().fill_bytes(&mut salt);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut options = fs::OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::OpenOptionsExt;
+                options.mode(0o600);
+            }
+            options.open(path)?.write_all(&salt)?;
+            Ok(salt)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peers() -> Vec<PeerId> {
+        (0..200).map(|_| PeerId::random()).collect()
+    }
+
+    fn distance_km((lat1, lon1): (f64, f64), (lat2, lon2): (f64, f64)) -> f64 {
+        let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+        let dlat = lat2 - lat1;
+        let dlon = (lon2 - lon1).to_radians();
+        let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+        2.0 * 6371.0 * a.sqrt().asin()
+    }
+
+    #[test]
+    fn deterministic_per_salt() {
+        let peer = PeerId::random();
+        let fuzzer = Fuzzer::new(FuzzMode::Offset, 50.0, [1; 32]);
+        let placed = fuzzer.apply(&peer, 48.85, 2.35);
+        assert_eq!(
+            Fuzzer::new(FuzzMode::Offset, 50.0, [1; 32]).apply(&peer, 48.85, 2.35),
+            placed
+        );
+        assert_ne!(
+            Fuzzer::new(FuzzMode::Offset, 50.0, [2; 32]).apply(&peer, 48.85, 2.35),
+            placed
+        );
+        assert_eq!(fuzzer.anywhere(&peer), fuzzer.anywhere(&peer));
+    }
+
+    #[test]
+    fn offset_stays_within_radius() {
+        let fuzzer = Fuzzer::new(FuzzMode::Offset, 50.0, [7; 32]);
+        for origin in [
+            (48.85, 2.35),
+            (-33.87, 151.21),
+            (64.13, -21.9),
+            (0.0, 179.9),
+        ] {
+            for peer in peers() {
+                let placed = fuzzer.apply(&peer, origin.0, origin.1);
+                // The flat-earth offset is a little off the true distance; allow for it
+                assert!(
+                    distance_km(origin, placed) <= 50.5,
+                    "{:?} -> {:?}",
+                    origin,
+                    placed
+                );
+                assert!((-180.0..180.0).contains(&placed.1));
+            }
+        }
+    }
+
+    #[test]
+    fn grid_snaps_to_the_cell_centre() {
+        let fuzzer = Fuzzer::new(FuzzMode::Grid, 50.0, [7; 32]);
+        let step = 50.0 / KM_PER_DEGREE;
+        let centre = fuzzer.apply(&PeerId::random(), 48.85, 2.35);
+        assert!((centre.0 - 48.85).abs() <= step / 2.0 && (centre.1 - 2.35).abs() <= step / 2.0);
+        for peer in peers() {
+            // Every peer, anywhere in the same cell, lands on the same point
+            assert_eq!(fuzzer.apply(&peer, 48.85, 2.35), centre);
+            assert_eq!(
+                fuzzer.apply(&peer, centre.0 + step * 0.4, centre.1 - step * 0.4),
+                centre
+            );
+        }
+    }
+
+    #[test]
+    fn salt_persists() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("geo-salt");
+        let salt = load_or_create_salt(&path).unwrap();
+        assert_eq!(load_or_create_salt(&path).unwrap(), salt);
+        fs::write(&path, b"short").unwrap();
+        assert!(load_or_create_salt(&path).is_err());
+    }
+}
@@ -0,0 +1,86 @@
+// Standard base-32 geohash: each character halves the cell five more times, alternating
+// longitude and latitude bits, so a shorter hash is simply a coarser location.
+const ALPHABET: &[u8; 32] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+pub fn encode(lat: f64, lon: f64, len: usize) -> String {
+    let (mut lat_range, mut lon_range) = ((-90.0, 90.0), (-180.0, 180.0));
+    let mut hash = String::with_capacity(len);
+    let mut even = true;
+    for _ in 0..len {
+        let mut index = 0;
+        for _ in 0..5 {
+            let (range, value) = if even {
+                (&mut lon_range, lon)
+            } else {
+                (&mut lat_range, lat)
+            };
+            let mid = (range.0 + range.1) / 2.0;
+            index <<= 1;
+            if value >= mid {
+                index |= 1;
+                range.0 = mid;
+            } else {
+                range.1 = mid;
+            }
+            even = !even;
+        }
+        hash.push(ALPHABET[index] as char);
+    }
+    hash
+}
+
+// Centre of the cell, or None for an empty or malformed hash.
+pub fn decode(hash: &str) -> Option<(f64, f64)> {
+    if hash.is_empty() || hash.len() > 12 {
+        return None;
+    }
+    let (mut lat_range, mut lon_range) = ((-90.0, 90.0), (-180.0, 180.0));
+    let mut even = true;
+    for c in hash.bytes() {
+        let index = ALPHABET.iter().position(|&a| a == c.to_ascii_lowercase())?;
+        for bit in (0..5).rev() {
+            let range = if even { &mut lon_range } else { &mut lat_range };
+            let mid = (range.0 + range.1) / 2.0;
+            if index >> bit & 1 == 1 {
+                range.0 = mid;
+            } else {
+                range.1 = mid;
+            }
+            even = !even;
+        }
+    }
+    Some((
+        (lat_range.0 + lat_range.1) / 2.0,
+        (lon_range.0 + lon_range.1) / 2.0,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_known_vectors() {
+        assert_eq!(encode(57.64911, 10.40744, 11), "u4pruydqqvj");
+        assert_eq!(encode(42.605, -5.603, 5), "ezs42");
+        assert_eq!(encode(-33.8688, 151.2093, 6), "r3gx2f");
+    }
+
+    #[test]
+    fn decodes_to_cell_centre() {
+        let (lat, lon) = decode("ezs42").unwrap();
+        assert!((lat - 42.605).abs() < 0.03 && (lon - -5.603).abs() < 0.03);
+        assert_eq!(decode("EZS42"), decode("ezs42"));
+        assert_eq!(decode(""), None);
+        assert_eq!(decode("ezs4a"), None); // 'a' isn't in the alphabet
+    }
+
+    #[test]
+    fn round_trips_within_precision() {
+        for &(lat, lon) in &[(51.5074, -0.1278), (-33.8688, 151.2093), (0.0, 0.0)] {
+            let (decoded_lat, decoded_lon) = decode(&encode(lat, lon, 8)).unwrap();
+            assert!((decoded_lat - lat).abs() < 0.0002);
+            assert!((decoded_lon - lon).abs() < 0.0002);
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use super::{builtin, GeoProvider, Location};
+use crate::config::ManualLocation;
+use std::net::IpAddr;
+
+// Places addresses the user has mapped by hand, e.g. an office subnet to its city. Entries
+// are checked in order and the first whose network contains the address wins.
+pub struct ManualProvider {
+    entries: Vec<(Network, Location)>,
+}
+
+impl ManualProvider {
+    pub fn new(entries: &[ManualLocation]) -> Result<Self, String> {
+        let entries = entries
+            .iter()
+            .map(|entry| {
+                let network = Network::parse(&entry.network)
+                    .ok_or_else(|| format!("geo.manual: invalid network {:?}", entry.network))?;
+                Ok((network, resolve(entry)?))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self { entries })
+    }
+}
+
+impl GeoProvider for ManualProvider {
+    fn locate(&self, ip: IpAddr) -> Option<Location> {
+        self.entries
+            .iter()
+            .find(|(network, _)| network.contains(ip))
+            .map(|(_, location)| location.clone())
+    }
+}
+
+// Either explicit coordinates or a country code standing in for its centroid.
+fn resolve(entry: &ManualLocation) -> Result<Location, String> {
+    let mut location = match (entry.lat, entry.lon, &entry.country) {
+        (Some(lat), Some(lon), _) => {
+            if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+                return Err(format!(
+                    "geo.manual: {}: coordinates out of range",
+                    entry.network
+                ));
+            }
+            Location {
+                lat,
+                lon,
+                label: entry.network.clone(),
+            }
+        }
+        (None, None, Some(code)) => builtin::country(code)
+            .ok_or_else(|| format!("geo.manual: {}: unknown country {:?}", entry.network, code))?,
+        _ => {
+            return Err(format!(
+                "geo.manual: {}: needs both lat and lon, or a country",
+                entry.network
+            ))
+        }
+    };
+    if let Some(label) = &entry.label {
+        location.label = label.clone();
+    }
+    Ok(location)
+}
+
+// An address block in CIDR notation; a bare address is a block of one.
+struct Network {
+    addr: IpAddr,
+    prefix: u32,
+}
+
+impl Network {
+    fn parse(input: &str) -> Option<Self> {
+        let (addr, prefix) = match input.split_once('/') {
+            Some((addr, prefix)) => (addr.parse::<IpAddr>().ok()?, Some(prefix.parse().ok()?)),
+            None => (input.parse::<IpAddr>().ok()?, None),
+        };
+        let bits = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(bits);
+        (prefix <= bits).then_some(Self { addr, prefix })
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use super::{builtin, GeoProvider, Location};
+use maxminddb::geoip2::{City, Country};
+use maxminddb::Reader;
+use std::net::IpAddr;
+use std::path::Path;
+
+fn open_reader(path: &Path) -> Result<Reader<Vec<u8>>, String> {
+    Reader::open_readfile(path)
+        .map_err(|e| format!("Failed to load MaxMind DB at {}: {}", path.display(), e))
+}
+
+// GeoLite2/GeoIP2 City: coordinates down to the city, so the resolver blurs them.
+pub struct MaxMindCity {
+    reader: Reader<Vec<u8>>,
+}
+
+impl MaxMindCity {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        Ok(Self {
+            reader: open_reader(path)?,
+        })
+    }
+}
+
+impl GeoProvider for MaxMindCity {
+    fn precise(&self) -> bool {
+        true
+    }
+
+    fn locate(&self, ip: IpAddr) -> Option<Location> {
+        let city = self.reader.lookup(ip).ok()?.decode::<City>().ok()??;
+        let lat = city.location.latitude?;
+        let lon = city.location.longitude?;
+
+        let mut place_name = String::new();
+        if let Some(en) = city.city.names.english {
+            place_name.push_str(en);
+        }
+
+        if let Some(iso) = city.country.iso_code {
+            if !place_name.is_empty() {
+                place_name.push_str(", ");
+            }
+            place_name.push_str(iso);
+        }
+
+        if place_name.is_empty() {
+            place_name = "Unknown".to_string();
+        }
+
+        Some(Location {
+            lat,
+            lon,
+            label: place_name,
+        })
+    }
+}
+
+// GeoLite2/GeoIP2 Country: the database has no coordinates, so the country (or failing
+// that, continent) is pinned to its built-in centroid.
+pub struct MaxMindCountry {
+    reader: Reader<Vec<u8>>,
+}
+
+impl MaxMindCountry {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        Ok(Self {
+            reader: open_reader(path)?,
+        })
+    }
+}
+
+impl GeoProvider for MaxMindCountry {
+    fn locate(&self, ip: IpAddr) -> Option<Location> {
+        let record = self.reader.lookup(ip).ok()?.decode::<Country>().ok()??;
+        if let Some(mut location) = record.country.iso_code.and_then(builtin::country) {
+            if let Some(name) = record.country.names.english {
+                location.label = name.to_string();
+            }
+            return Some(location);
+        }
+        record.continent.code.and_then(builtin::continent)
+    }
+}
@@ -0,0 +1,158 @@
+mod builtin;
+mod fuzz;
+pub mod geohash;
+mod manual;
+mod maxmind;
+
+pub use builtin::BuiltinRanges;
+pub use fuzz::Fuzzer;
+pub use manual::ManualProvider;
+pub use maxmind::{MaxMindCity, MaxMindCountry};
+
+use crate::config::{GeoBackend, GeoConfig};
+use libp2p::PeerId;
+use std::net::IpAddr;
+
+// Where a peer was placed; `label` is what the network panel shows for them.
+#[derive(Debug, Clone)]
+pub struct Location {
+    pub lat: f64,
+    pub lon: f64,
+    pub label: String,
+}
+
+// Turns a peer's address into a spot on the globe, or None if it has no idea.
+pub trait GeoProvider {
+    fn locate(&self, ip: IpAddr) -> Option<Location>;
+
+    // Whether results are exact enough to give someone away, and so get fuzzed
+    fn precise(&self) -> bool {
+        false
+    }
+}
+
+// Asks each provider in turn: manual entries first since the user wrote them, then the
+// configured backend, then the built-in table so peers still get an approximate marker.
+pub struct GeoResolver {
+    providers: Vec<Box<dyn GeoProvider>>,
+    fuzzer: Fuzzer,
+    notice: Option<String>, // why the configured backend isn't in use, for the chat feed
+}
+
+impl Default for GeoResolver {
+    fn default() -> Self {
+        Self::from_config(&GeoConfig::default()).unwrap_or_else(|_| Self {
+            providers: vec![Box::new(BuiltinRanges)],
+            fuzzer: Fuzzer::new(crate::config::FuzzMode::Off, 0.0, rand::random()),
+            notice: None,
+        })
+    }
+}
+
+impl GeoResolver {
+    pub fn from_config(geo: &GeoConfig) -> Result<Self, String> {
+        let mut providers: Vec<Box<dyn GeoProvider>> = Vec::new();
+        let mut notice = None;
+        if !geo.manual.is_empty() {
+            providers.push(Box::new(ManualProvider::new(&geo.manual)?));
+        }
+
+        // A missing or broken database never stops startup, it just leaves the fallback
+        let database = match geo.provider {
+            GeoBackend::MaxmindCity => {
+                Some(MaxMindCity::open(&geo.db_path).map(|p| Box::new(p) as Box<dyn GeoProvider>))
+            }
+            GeoBackend::MaxmindCountry => Some(
+                MaxMindCountry::open(&geo.db_path).map(|p| Box::new(p) as Box<dyn GeoProvider>),
+            ),
+            GeoBackend::Builtin | GeoBackend::Manual => None,
+        };
+        match database {
+            Some(Ok(provider)) => providers.push(provider),
+            Some(Err(_)) if !geo.db_path.exists() => {
+                notice = Some(format!(
+                    "No GeoLite database at {}; peers are placed roughly. Run `terra-link geo update` (with geo.sha256 set, or --allow-unverified) for city-level markers.",
+                    geo.db_path.display()
+                ));
+            }
+            Some(Err(e)) => {
+                notice = Some(format!("{}; peers are placed roughly instead.", e));
+            }
+            None => {}
+        }
+
+        if geo.provider != GeoBackend::Manual {
+            providers.push(Box::new(BuiltinRanges));
+        }
+
+        // Losing the salt only moves everyone's marker once, so don't refuse to start over it
+        let salt_path = crate::keystore::data_dir().join("geo-salt");
+        let salt = fuzz::load_or_create_salt(&salt_path).unwrap_or_else(|e| {
+            tracing::warn!("Using a throwaway fuzz salt: {}", e);
+            rand::random()
+        });
+        let fuzzer = Fuzzer::new(geo.fuzz, geo.fuzz_km, salt);
+        Ok(Self {
+            providers,
+            fuzzer,
+            notice,
+        })
+    }
+
+    pub fn notice(&self) -> Option<&str> {
+        self.notice.as_deref()
+    }
+
+    pub fn get_fuzzed_location(&self, peer: &PeerId, ip: IpAddr) -> Option<(f64, f64, String)> {
+        // Fallback for local testing loopback
+        if ip.is_loopback() {
+            let (lat, lon) = self.fuzzer.anywhere(peer);
+            return Some((lat, lon, "Localhost".to_string()));
+        }
+
+        self.providers.iter().find_map(|provider| {
+            let location = provider.locate(ip)?;
+            let (lat, lon) = if provider.precise() {
+                self.fuzzer.apply(peer, location.lat, location.lon)
+            } else {
+                (location.lat, location.lon)
+            };
+            Some((lat, lon, location.label))
+        })
+    }
+}
+
+// A location a peer declared in its presence, placed at the centre of its geohash cell and
+// labelled with how coarse the peer chose to be.
+pub fn declared_location(hash: &str) -> Option<(f64, f64, String)> {
+    let (lat, lon) = geohash::decode(hash)?;
+    let level = match hash.len() {
+        1 => "continent",
+        2..=3 => "country",
+        4..=7 => "city",
+        _ => "exact",
+    };
+    Some((lat, lon, format!("Shared, {}-level", level)))
+}
+
+// Whether an address could tell us anything about where its owner is.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            !(v4.is_private()
+                || v4.is_loopback()
+                || v4.is_link_local()
+                || v4.is_unspecified()
+                // 100.64.0.0/10, carrier-grade NAT
+                || (v4.octets()[0] == 100 && v4.octets()[1] & 0xc0 == 64))
+        }
+        IpAddr::V6(v6) => {
+            let first = v6.segments()[0];
+            // Unique local fc00::/7 and link-local fe80::/10
+            !(v6.is_loopback()
+                || v6.is_unspecified()
+                || first & 0xfe00 == 0xfc00
+                || first & 0xffc0 == 0xfe80)
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use crate::config::GeoConfig;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// A community mirror of MaxMind's free GeoLite2 City database.
+pub const DEFAULT_SOURCE_URL: &str =
+    "https://github.com/P3TERX/GeoLite.mmdb/raw/download/GeoLite2-City.mmdb";
+
+pub fn default_path() -> PathBuf {
+    crate::keystore::data_dir().join("GeoLite2-City.mmdb")
+}
+
+// `geo update`: fetches the database into `<db_path>.part`, resuming an interrupted
+// download, verifies it, then renames it over the old one so a reader never sees a
+// half-written file. Without a checksum to verify against it refuses to install unless
+// `allow_unverified` is set.
+pub async fn update(geo: &GeoConfig, allow_unverified: bool) -> io::Result<()> {
+    // Known before downloading, so a missing checksum fails fast
+    let expected = match (&geo.sha256, &geo.sha256_url) {
+        (Some(hash), _) => Some(hash.trim().to_ascii_lowercase()),
+        (None, Some(url)) => Some(fetch_checksum(url).await?),
+        (None, None) if allow_unverified => None,
+        (None, None) => {
+            return Err(io::Error::other(
+                "No geo.sha256 or geo.sha256_url configured, so the download can't be \
+                 verified. Set one, or pass --allow-unverified to install it anyway.",
+            ))
+        }
+    };
+
+    if let Some(parent) = geo.db_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let part = geo.db_path.with_extension("mmdb.part");
+    let validator = geo.db_path.with_extension("mmdb.part.validator");
+    println!("Downloading {}", geo.source_url);
+    download(&geo.source_url, &part, &validator).await?;
+
+    let actual = sha256_file(&part)?;
+    match expected {
+        Some(expected) if expected != actual => {
+            // Resuming onto a bad file would only fail again
+            discard(&part, &validator)?;
+            return Err(io::Error::other(format!(
+                "Checksum mismatch: expected {}, got {}",
+                expected, actual
+            )));
+        }
+        Some(_) => println!("Checksum verified."),
+        None => println!("Installing unverified download. SHA-256: {}", actual),
+    }
+
+    // A mirror serving an error page would otherwise only show up as failed lookups
+    if let Err(e) = maxminddb::Reader::open_readfile(&part) {
+        discard(&part, &validator)?;
+        return Err(io::Error::other(format!(
+            "Downloaded file is not a MaxMind database: {}",
+            e
+        )));
+    }
+
+    fs::rename(&part, &geo.db_path)?;
+    let _ = fs::remove_file(&validator);
+    println!("Installed {}", geo.db_path.display());
+    Ok(())
+}
+
+fn discard(part: &Path, validator: &Path) -> io::Result<()> {
+    let _ = fs::remove_file(validator);
+    fs::remove_file(part)
+}
+
+// Resumes only with the ETag (or Last-Modified) the partial file was fetched under, sent
+// as If-Range, so a database republished in between is fetched whole instead of having
+// its tail spliced onto the old one's start.
+async fn download(url: &str, part: &Path, validator: &Path) -> io::Result<()> {
+    let offset = fs::metadata(part).map(|m| m.len()).unwrap_or(0);
+    let stored = fs::read_to_string(validator).ok();
+    let mut request = reqwest::Client::new().get(url);
+    if let Some(stored) = stored.as_deref().filter(|_| offset > 0) {
+        request = request
+            .header(reqwest::header::RANGE, format!("bytes={}-", offset))
+            .header(reqwest::header::IF_RANGE, stored.trim());
+    }
+    let mut response = request
+        .send()
+        .await
+        .map_err(|e| io::Error::other(format!("Download failed: {}", e)))?;
+
+    let resuming = match response.status() {
+        reqwest::StatusCode::PARTIAL_CONTENT => true,
+        // The previous attempt got everything; verification decides if it's any good
+        reqwest::StatusCode::RANGE_NOT_SATISFIABLE => return Ok(()),
+        status if status.is_success() => false,
+        status => {
+            return Err(io::Error::other(format!(
+                "Download failed: HTTP {}",
+                status
+            )))
+        }
+    };
+    let (mut file, mut done) = if resuming {
+        println!("Resuming after {} MB", offset / 1_000_000);
+        (fs::OpenOptions::new().append(true).open(part)?, offset)
+    } else {
+        // Starting from zero, under whatever version the server is sending now
+        let headers = response.headers();
+        let current = headers
+            .get(reqwest::header::ETAG)
+            .or_else(|| headers.get(reqwest::header::LAST_MODIFIED))
+            .and_then(|value| value.to_str().ok());
+        match current {
+            Some(current) => fs::write(validator, current)?,
+            // Nothing to resume against next time
+            None => {
+                let _ = fs::remove_file(validator);
+            }
+        }
+        (fs::File::create(part)?, 0)
+    };
+    let total = response.content_length().map(|len| len + done);
+
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| io::Error::other(format!("Failed to read chunk: {}", e)))?
+    {
+        file.write_all(&chunk)?;
+        done += chunk.len() as u64;
+        match total {
+            Some(total) => print!("\r{} / {} MB", done / 1_000_000, total / 1_000_000),
+            None => print!("\r{} MB", done / 1_000_000),
+        }
+        let _ = io::stdout().flush();
+    }
+    println!();
+    file.sync_all()
+}
+
+// Accepts a bare hash or `sha256sum` output ("<hash>  <file>").
+async fn fetch_checksum(url: &str) -> io::Result<String> {
+    let text = reqwest::get(url)
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| io::Error::other(format!("Checksum download failed: {}", e)))?
+        .text()
+        .await
+        .map_err(|e| io::Error::other(format!("Checksum download failed: {}", e)))?;
+    let hash = text.split_whitespace().next().unwrap_or_default();
+    if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(io::Error::other(format!(
+            "{} does not contain a SHA-256 checksum",
+            url
+        )));
+    }
+    Ok(hash.to_ascii_lowercase())
+}
+
+fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
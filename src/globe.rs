@@ -1,3 +1,4 @@
+use chrono::{DateTime, Datelike, Timelike, Utc};
 use ratatui::style::Color;
 
 pub const EARTH_MAP_WIDTH: usize = 64;
@@ -38,6 +39,296 @@ pub const EARTH_MAP: [&str; EARTH_MAP_HEIGHT] = [
     "                                                                ",
 ];
 
+// 1° land mask for the sub-cell renderers: 360 columns from 180°W, 180 rows from 90°N,
+// '#' for land.
+const LAND_MASK: &str = include_str!("land_mask.txt");
+const LAND_MASK_WIDTH: usize = 360;
+const LAND_MASK_HEIGHT: usize = 180;
+
+// How finely the globe is sampled: one sample per cell against `EARTH_MAP`, or several
+// per cell against `LAND_MASK`, drawn as half blocks (1x2) or braille dots (2x4).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Detail {
+    #[default]
+    Ascii,
+    HalfBlock,
+    Braille,
+}
+
+impl Detail {
+    // Samples per cell, across and down
+    pub fn sub_cells(self) -> (u16, u16) {
+        match self {
+            Detail::Ascii => (1, 1),
+            Detail::HalfBlock => (1, 2),
+            Detail::Braille => (2, 4),
+        }
+    }
+
+    fn map_size(self) -> (usize, usize) {
+        match self {
+            Detail::Ascii => (EARTH_MAP_WIDTH, EARTH_MAP_HEIGHT),
+            _ => (LAND_MASK_WIDTH, LAND_MASK_HEIGHT),
+        }
+    }
+
+    // Map row for a latitude in radians
+    pub fn map_row(self, lat: f64) -> usize {
+        let height = self.map_size().1;
+        let v = 1.0 - (lat + std::f64::consts::PI / 2.0) / std::f64::consts::PI;
+        ((v * height as f64) as usize).clamp(0, height - 1)
+    }
+
+    // `u` runs 0..1 from 180°W eastwards
+    pub fn is_land(self, row: usize, u: f64) -> bool {
+        let width = self.map_size().0;
+        let col = ((u * width as f64) as usize).clamp(0, width - 1);
+        match self {
+            Detail::Ascii => EARTH_MAP[row].as_bytes()[col] == b'#',
+            // Rows are newline-terminated
+            _ => LAND_MASK.as_bytes()[row * (LAND_MASK_WIDTH + 1) + col] == b'#',
+        }
+    }
+}
+
+// Discrete zoom steps, as multiples of the globe fitting the panel height.
+const ZOOM_LEVELS: [f64; 5] = [1.0, 1.5, 2.0, 3.0, 4.0];
+const SPIN_PER_TICK: f64 = 0.05;
+// Fraction of the remaining distance covered each tick while flying to a focus target
+const FOCUS_EASING: f64 = 0.25;
+
+// Where the globe is viewed from. `yaw` is the longitude at the centre of the view and
+// `tilt` the latitude, both in radians; tilting shows the poles.
+#[derive(Debug, Clone, Copy)]
+pub struct Camera {
+    pub yaw: f64,
+    pub tilt: f64,
+    zoom_level: usize,
+    pub spinning: bool,
+    target: Option<(f64, f64)>, // (yaw, tilt) being animated towards
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            yaw: 0.0,
+            tilt: 0.0,
+            zoom_level: 0,
+            spinning: true,
+            target: None,
+        }
+    }
+}
+
+impl Camera {
+    pub fn zoom(&self) -> f64 {
+        ZOOM_LEVELS[self.zoom_level]
+    }
+
+    pub fn tick(&mut self) {
+        if let Some((yaw, tilt)) = self.target {
+            // The short way round, whichever side of the date line we're on
+            let dyaw = (yaw - self.yaw + std::f64::consts::PI).rem_euclid(std::f64::consts::TAU)
+                - std::f64::consts::PI;
+            let dtilt = tilt - self.tilt;
+            if dyaw.abs() < 0.005 && dtilt.abs() < 0.005 {
+                self.yaw = yaw;
+                self.tilt = tilt;
+                self.target = None;
+            } else {
+                self.yaw += dyaw * FOCUS_EASING;
+                self.tilt += dtilt * FOCUS_EASING;
+            }
+        } else if self.spinning {
+            self.yaw += SPIN_PER_TICK;
+        }
+        self.yaw = self.yaw.rem_euclid(std::f64::consts::TAU);
+    }
+
+    // Manual moves cancel a focus flight in progress. Zoomed in, the same drag covers less
+    // of the globe.
+    pub fn rotate(&mut self, dyaw: f64, dtilt: f64) {
+        self.target = None;
+        self.yaw = (self.yaw + dyaw / self.zoom()).rem_euclid(std::f64::consts::TAU);
+        self.tilt = (self.tilt + dtilt / self.zoom())
+            .clamp(-std::f64::consts::FRAC_PI_2, std::f64::consts::FRAC_PI_2);
+    }
+
+    pub fn zoom_in(&mut self) {
+        self.zoom_level = (self.zoom_level + 1).min(ZOOM_LEVELS.len() - 1);
+    }
+
+    pub fn zoom_out(&mut self) {
+        self.zoom_level = self.zoom_level.saturating_sub(1);
+    }
+
+    // Flies to put `lat`/`lon` (degrees) in the middle, and stops spinning so it stays there.
+    pub fn focus(&mut self, lat: f64, lon: f64) {
+        self.spinning = false;
+        self.target = Some((lon.to_radians(), lat.to_radians()));
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self {
+            yaw: self.yaw,
+            ..Self::default()
+        };
+    }
+
+    // Camera-space position of a point on the unit globe: x right, y up, z towards the
+    // viewer. Only points with z > 0 are on the visible side.
+    pub fn project(&self, lat: f64, lon: f64) -> (f64, f64, f64) {
+        let (lat, lon) = (lat.to_radians(), lon.to_radians() - self.yaw);
+        let (x, y, z) = (lat.cos() * lon.sin(), lat.sin(), lat.cos() * lon.cos());
+        let (sin, cos) = self.tilt.sin_cos();
+        (x, y * cos - z * sin, y * sin + z * cos)
+    }
+
+    // Undoes the tilt for a camera-space direction, leaving only the yaw between the
+    // result and the globe's own axes.
+    pub fn untilt(&self, (x, y, z): (f64, f64, f64)) -> (f64, f64, f64) {
+        let (sin, cos) = self.tilt.sin_cos();
+        (x, y * cos + z * sin, z * cos - y * sin)
+    }
+}
+
+// Robinson's table at 5° steps of latitude from the equator: the length of the parallel
+// relative to the equator's, and its distance from the equator relative to the pole's.
+const ROBINSON: [(f64, f64); 19] = [
+    (1.0000, 0.0000),
+    (0.9986, 0.0620),
+    (0.9954, 0.1240),
+    (0.9900, 0.1860),
+    (0.9822, 0.2480),
+    (0.9730, 0.3100),
+    (0.9600, 0.3720),
+    (0.9427, 0.4340),
+    (0.9216, 0.4958),
+    (0.8962, 0.5571),
+    (0.8679, 0.6176),
+    (0.8350, 0.6769),
+    (0.7986, 0.7346),
+    (0.7597, 0.7903),
+    (0.7186, 0.8435),
+    (0.6732, 0.8936),
+    (0.6213, 0.9394),
+    (0.5722, 0.9761),
+    (0.5322, 1.0000),
+];
+
+// The whole world at once, for the flat view. Map coordinates run -1..1 across and up,
+// latitude and longitude are in radians with longitude 0 in the middle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlatMap {
+    Equirectangular,
+    Robinson,
+}
+
+impl FlatMap {
+    // Width over height of the whole map
+    pub fn aspect(self) -> f64 {
+        match self {
+            FlatMap::Equirectangular => 2.0,
+            FlatMap::Robinson => 0.8487 * std::f64::consts::PI / 1.3523,
+        }
+    }
+
+    pub fn project(self, lat: f64, lon: f64) -> (f64, f64) {
+        let x = lon / std::f64::consts::PI;
+        match self {
+            FlatMap::Equirectangular => (x, lat / std::f64::consts::FRAC_PI_2),
+            FlatMap::Robinson => {
+                let (length, distance) = robinson_row(lat.abs());
+                (x * length, distance.copysign(lat))
+            }
+        }
+    }
+
+    // None outside the map's outline
+    pub fn unproject(self, x: f64, y: f64) -> Option<(f64, f64)> {
+        if x.abs() > 1.0 || y.abs() > 1.0 {
+            return None;
+        }
+        match self {
+            FlatMap::Equirectangular => {
+                Some((y * std::f64::consts::FRAC_PI_2, x * std::f64::consts::PI))
+            }
+            FlatMap::Robinson => {
+                let lat = robinson_latitude(y.abs()).copysign(y);
+                let x = x / robinson_row(lat.abs()).0;
+                (x.abs() <= 1.0).then_some((lat, x * std::f64::consts::PI))
+            }
+        }
+    }
+}
+
+// Robinson's table interpolated at a latitude in 0..=π/2
+fn robinson_row(lat: f64) -> (f64, f64) {
+    let step = lat.to_degrees() / 5.0;
+    let i = (step as usize).min(ROBINSON.len() - 2);
+    let t = step - i as f64;
+    let (a, b) = (ROBINSON[i], ROBINSON[i + 1]);
+    (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t)
+}
+
+// The latitude whose parallel sits at `distance` (0..=1) from the equator
+fn robinson_latitude(distance: f64) -> f64 {
+    let i = ROBINSON
+        .windows(2)
+        .position(|pair| distance <= pair[1].1)
+        .unwrap_or(ROBINSON.len() - 2);
+    let (a, b) = (ROBINSON[i].1, ROBINSON[i + 1].1);
+    ((i as f64 + (distance - a) / (b - a)) * 5.0).to_radians()
+}
+
+// Spacing of the points along a connection arc.
+const ARC_STEP_DEGREES: f64 = 2.0;
+
+// Points along the shorter great circle between two (lat, lon) positions in degrees,
+// both ends included.
+pub fn great_circle(from: (f64, f64), to: (f64, f64)) -> Vec<(f64, f64)> {
+    let unit = |(lat, lon): (f64, f64)| {
+        let (lat, lon) = (lat.to_radians(), lon.to_radians());
+        (lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin())
+    };
+    let (a, b) = (unit(from), unit(to));
+    let angle = (a.0 * b.0 + a.1 * b.1 + a.2 * b.2).clamp(-1.0, 1.0).acos();
+    // Same place, or exactly opposite where every great circle would do
+    if angle.sin() < 1e-9 {
+        return vec![from, to];
+    }
+    let steps = (angle.to_degrees() / ARC_STEP_DEGREES).ceil() as usize;
+    (0..=steps)
+        .map(|i| {
+            let t = i as f64 / steps as f64;
+            let (wa, wb) = (
+                ((1.0 - t) * angle).sin() / angle.sin(),
+                (t * angle).sin() / angle.sin(),
+            );
+            let (x, y, z) = (
+                wa * a.0 + wb * b.0,
+                wa * a.1 + wb * b.1,
+                wa * a.2 + wb * b.2,
+            );
+            (
+                z.clamp(-1.0, 1.0).asin().to_degrees(),
+                y.atan2(x).to_degrees(),
+            )
+        })
+        .collect()
+}
+
+// Braille cell with the given dots raised; bit `col * 4 + row` is the dot at that
+// position in the 2x4 grid.
+pub fn braille(dots: u8) -> char {
+    // Unicode numbers dots 1-3 and 4-6 down each column, with dots 7 and 8 added below
+    const BITS: [u32; 8] = [0x01, 0x02, 0x04, 0x40, 0x08, 0x10, 0x20, 0x80];
+    let code = (0..8)
+        .filter(|i| dots & (1 << i) != 0)
+        .fold(0, |acc, i| acc | BITS[i]);
+    char::from_u32(0x2800 + code).unwrap_or(' ')
+}
+
 // Day side land: hot magenta/neon pink
 const LAND_DAY_R: f64 = 255.0;
 const LAND_DAY_G: f64 = 45.0;
@@ -58,8 +349,77 @@ const OCEAN_NIGHT_R: f64 = 10.0;
 const OCEAN_NIGHT_G: f64 = 0.0;
 const OCEAN_NIGHT_B: f64 = 25.0;
 
+// Twilight band: dusk glow fading out until the sun is 18° below the horizon
+// (astronomical twilight), i.e. intensity down to -sin(18°).
+const TWILIGHT_DEPTH: f64 = 0.309;
+const LAND_DUSK: (f64, f64, f64) = (170.0, 40.0, 120.0);
+const OCEAN_DUSK: (f64, f64, f64) = (70.0, 30.0, 130.0);
+
+// Latitude and longitude in degrees of the point where the sun is directly overhead,
+// using NOAA's low-precision solar equations (good to a fraction of a degree).
+pub fn subsolar_point(now: DateTime<Utc>) -> (f64, f64) {
+    let hours = now.hour() as f64 + now.minute() as f64 / 60.0 + now.second() as f64 / 3600.0;
+    // Fractional year, in radians
+    let g = 2.0 * std::f64::consts::PI / 365.0 * (now.ordinal0() as f64 + (hours - 12.0) / 24.0);
+
+    let declination = 0.006918 - 0.399912 * g.cos() + 0.070257 * g.sin()
+        - 0.006758 * (2.0 * g).cos()
+        + 0.000907 * (2.0 * g).sin()
+        - 0.002697 * (3.0 * g).cos()
+        + 0.00148 * (3.0 * g).sin();
+    // Minutes the true sun runs ahead of clock time
+    let equation_of_time = 229.18
+        * (0.000075 + 0.001868 * g.cos()
+            - 0.032077 * g.sin()
+            - 0.014615 * (2.0 * g).cos()
+            - 0.040849 * (2.0 * g).sin());
+
+    let lon = -15.0 * (hours - 12.0 + equation_of_time / 60.0);
+    (
+        declination.to_degrees(),
+        (lon + 180.0).rem_euclid(360.0) - 180.0,
+    )
+}
+
+fn blend(from: (f64, f64, f64), to: (f64, f64, f64), t: f64) -> Color {
+    let mix = |a: f64, b: f64| (a + (b - a) * t) as u8;
+    Color::Rgb(mix(from.0, to.0), mix(from.1, to.1), mix(from.2, to.2))
+}
+
 // Map a land/ocean point and its Lambertian intensity to a character and neon color.
-pub fn get_appearance(is_land: bool, intensity: f64) -> (char, Color) {
+// With `twilight`, points just past the terminator get a dusk band instead of going dark.
+pub fn get_appearance(is_land: bool, intensity: f64, twilight: bool) -> (char, Color) {
+    if twilight && intensity <= 0.0 && intensity > -TWILIGHT_DEPTH {
+        // 1 at the terminator, 0 where night proper begins
+        let t = 1.0 + intensity / TWILIGHT_DEPTH;
+        // Fades into exactly what the night side draws at the band's inner edge
+        let fade = 1.0 - TWILIGHT_DEPTH;
+        let (night, dusk) = if is_land {
+            let dim = LAND_NIGHT_BASE * fade;
+            (
+                (dim * LAND_NIGHT_R_FACTOR, 0.0, dim * LAND_NIGHT_B_FACTOR),
+                LAND_DUSK,
+            )
+        } else {
+            (
+                (
+                    OCEAN_NIGHT_R * fade,
+                    OCEAN_NIGHT_G * fade,
+                    OCEAN_NIGHT_B * fade,
+                ),
+                OCEAN_DUSK,
+            )
+        };
+        let char = if is_land {
+            '⣿'
+        } else if t > 0.5 {
+            '.'
+        } else {
+            ' '
+        };
+        return (char, blend(night, dusk, t));
+    }
+
     if intensity > 0.0 {
         // Day side : ambient + diffuse
         let i = 0.2 + (intensity * 0.8);
@@ -113,3 +473,83 @@ pub fn get_appearance(is_land: bool, intensity: f64) -> (char, Color) {
         (char, color)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flat_maps_round_trip() {
+        for map in [FlatMap::Equirectangular, FlatMap::Robinson] {
+            for lat in (-90..=90).step_by(5) {
+                for lon in (-175..=175).step_by(25) {
+                    let (lat, lon) = ((lat as f64).to_radians(), (lon as f64).to_radians());
+                    let (x, y) = map.project(lat, lon);
+                    assert!(x.abs() <= 1.0 && y.abs() <= 1.0);
+                    let (back_lat, back_lon) = map.unproject(x, y).unwrap();
+                    assert!((back_lat - lat).abs() < 1e-9, "{:?} lat {}", map, lat);
+                    assert!((back_lon - lon).abs() < 1e-9, "{:?} lon {}", map, lon);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn robinson_corners_are_off_the_map() {
+        // The poles are shorter than the equator, so the map's corners are empty
+        assert_eq!(FlatMap::Robinson.unproject(0.99, 0.99), None);
+        assert!(FlatMap::Robinson.unproject(0.99, 0.0).is_some());
+        assert_eq!(FlatMap::Robinson.unproject(1.5, 0.0), None);
+    }
+
+    #[test]
+    fn great_circle_runs_between_its_endpoints() {
+        let (london, sydney) = ((51.5, -0.1), (-33.9, 151.2));
+        let arc = great_circle(london, sydney);
+        let close =
+            |a: (f64, f64), b: (f64, f64)| (a.0 - b.0).abs() < 1e-6 && (a.1 - b.1).abs() < 1e-6;
+        assert!(close(arc[0], london));
+        assert!(close(*arc.last().unwrap(), sydney));
+        // Sampled every ARC_STEP_DEGREES along the way
+        let degrees_apart = |a: (f64, f64), b: (f64, f64)| {
+            let (a, b) = (
+                (a.0.to_radians(), a.1.to_radians()),
+                (b.0.to_radians(), b.1.to_radians()),
+            );
+            (a.0.sin() * b.0.sin() + a.0.cos() * b.0.cos() * (b.1 - a.1).cos())
+                .clamp(-1.0, 1.0)
+                .acos()
+                .to_degrees()
+        };
+        assert!(arc
+            .windows(2)
+            .all(|pair| degrees_apart(pair[0], pair[1]) <= ARC_STEP_DEGREES));
+        assert!(arc.len() > 70);
+        assert!(arc
+            .iter()
+            .all(|&(lat, lon)| lat.abs() <= 90.0 && lon.abs() <= 180.0));
+    }
+
+    #[test]
+    fn great_circle_degenerate_cases() {
+        assert_eq!(
+            great_circle((10.0, 20.0), (10.0, 20.0)),
+            vec![(10.0, 20.0), (10.0, 20.0)]
+        );
+        // Exactly opposite: no single shorter arc, so just the endpoints
+        assert_eq!(
+            great_circle((0.0, 0.0), (0.0, 180.0)),
+            vec![(0.0, 0.0), (0.0, 180.0)]
+        );
+        assert_eq!(
+            great_circle((90.0, 0.0), (-90.0, 0.0)),
+            vec![(90.0, 0.0), (-90.0, 0.0)]
+        );
+        // Nearly opposite still interpolates cleanly
+        let arc = great_circle((0.0, 0.0), (0.5, 179.5));
+        assert!(arc.len() > 2);
+        assert!(arc
+            .iter()
+            .all(|&(lat, lon)| lat.is_finite() && lon.is_finite()));
+    }
+}
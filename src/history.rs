@@ -0,0 +1,172 @@
+use crate::codec::ProtobufCodec;
+use crate::proto::messages::{network_message, HistoryRequest, HistoryResponse, NetworkMessage};
+use libp2p::{request_response, StreamProtocol};
+use std::collections::{HashMap, VecDeque};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const HISTORY_PROTOCOL: &str = "/terra-link/history/1.0.0";
+
+// How many recent payloads each node keeps per room to serve late joiners.
+const HISTORY_CAPACITY: usize = 200;
+
+pub type HistoryBehaviour =
+    request_response::Behaviour<ProtobufCodec<HistoryRequest, HistoryResponse>>;
+
+pub fn new_behaviour() -> HistoryBehaviour {
+    request_response::Behaviour::new(
+        [(
+            StreamProtocol::new(HISTORY_PROTOCOL),
+            request_response::ProtocolSupport::Full,
+        )],
+        request_response::Config::default(),
+    )
+}
+
+// Recent gossip payloads per room, kept exactly as they travelled on the wire.
+//
+// Storing raw payloads means private room traffic stays sealed: the relay (or any member)
+// can hand history to a late joiner without being able to read it, and only key holders can
+// open what they get back.
+#[derive(Default)]
+pub struct HistoryStore {
+    rooms: HashMap<String, VecDeque<(u64, Vec<u8>)>>, // room -> (received at, payload)
+}
+
+impl HistoryStore {
+    pub fn record(&mut self, room: &str, payload: &[u8]) {
+        use prost::Message;
+        // Presence and profiles are republished periodically, so replaying them is pointless;
+        // sealed payloads won't decode as either
+        if let Ok(NetworkMessage {
+            message_type:
+                Some(
+                    network_message::MessageType::Presence(_)
+                    | network_message::MessageType::Profile(_),
+                ),
+            ..
+        }) = NetworkMessage::decode(payload)
+        {
+            return;
+        }
+
+        let entries = self.rooms.entry(room.to_string()).or_default();
+        entries.push_back((now_millis(), payload.to_vec()));
+        if entries.len() > HISTORY_CAPACITY {
+            entries.pop_front();
+        }
+    }
+
+    // The newest `limit` payloads received after `since`, oldest first.
+    pub fn query(&self, request: &HistoryRequest) -> HistoryResponse {
+        let payloads = self
+            .rooms
+            .get(&request.room)
+            .map(|entries| {
+                let recent: Vec<&Vec<u8>> = entries
+                    .iter()
+                    .filter(|(received_at, _)| *received_at > request.since)
+                    .map(|(_, payload)| payload)
+                    .collect();
+                let skip = recent.len().saturating_sub(request.limit as usize);
+                recent.into_iter().skip(skip).cloned().collect()
+            })
+            .unwrap_or_default();
+
+        HistoryResponse {
+            room: request.room.clone(),
+            payloads,
+        }
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::messages::{GlobalChat, Presence, Profile};
+    use prost::Message;
+
+    fn payload(message_type: network_message::MessageType) -> Vec<u8> {
+        NetworkMessage {
+            message_type: Some(message_type),
+            ..Default::default()
+        }
+        .encode_to_vec()
+    }
+
+    fn chat(text: &str) -> Vec<u8> {
+        payload(network_message::MessageType::Chat(GlobalChat {
+            text: text.to_string(),
+            ..Default::default()
+        }))
+    }
+
+    fn everything(store: &HistoryStore, room: &str) -> Vec<Vec<u8>> {
+        store
+            .query(&HistoryRequest {
+                room: room.to_string(),
+                since: 0,
+                limit: u32::MAX,
+            })
+            .payloads
+    }
+
+    #[test]
+    fn keeps_the_newest_per_room() {
+        let mut store = HistoryStore::default();
+        for i in 0..HISTORY_CAPACITY + 50 {
+            store.record("/world", &chat(&i.to_string()));
+        }
+        store.record("/dev", &chat("other room"));
+
+        let kept = everything(&store, "/world");
+        assert_eq!(kept.len(), HISTORY_CAPACITY);
+        assert_eq!(kept[0], chat("50"));
+        assert_eq!(
+            kept.last(),
+            Some(&chat(&(HISTORY_CAPACITY + 49).to_string()))
+        );
+        assert_eq!(everything(&store, "/dev"), vec![chat("other room")]);
+    }
+
+    #[test]
+    fn skips_presence_and_profiles() {
+        let mut store = HistoryStore::default();
+        store.record(
+            "/world",
+            &payload(network_message::MessageType::Presence(Presence::default())),
+        );
+        store.record(
+            "/world",
+            &payload(network_message::MessageType::Profile(Profile::default())),
+        );
+        store.record("/world", &chat("hi"));
+        // Sealed private room payloads can't be told apart, so they are kept
+        store.record("/world", &[0xff, 0x00, 0x13]);
+        assert_eq!(
+            everything(&store, "/world"),
+            vec![chat("hi"), vec![0xff, 0x00, 0x13]]
+        );
+    }
+
+    #[test]
+    fn query_limit_returns_newest_oldest_first() {
+        let mut store = HistoryStore::default();
+        for text in ["a", "b", "c"] {
+            store.record("/world", &chat(text));
+        }
+        let response = store.query(&HistoryRequest {
+            room: "/world".to_string(),
+            since: 0,
+            limit: 2,
+        });
+        assert_eq!(response.room, "/world");
+        assert_eq!(response.payloads, vec![chat("b"), chat("c")]);
+    }
+}
@@ -0,0 +1,64 @@
+use libp2p::identity::Keypair;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// Per-user directory for persistent node state (keys, caches).
+// Falls back to the working directory if the platform has no data dir.
+pub fn data_dir() -> PathBuf {
+    dirs::data_dir()
+        .map(|d| d.join("terra-link"))
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+// Default location of a named keypair file inside the data dir.
+pub fn default_identity_path(name: &str) -> PathBuf {
+    data_dir().join(format!("{name}.key"))
+}
+
+// Load the keypair stored at `path`, generating and saving a fresh ed25519 one if absent.
+pub fn load_or_create(path: &Path) -> io::Result<Keypair> {
+    match fs::read(path) {
+        Ok(bytes) => Keypair::from_protobuf_encoding(&bytes).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Corrupt identity file {}: {}", path.display(), e),
+            )
+        }),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => rotate(path),
+        Err(e) => Err(e),
+    }
+}
+
+// Replace the keypair at `path` with a newly generated one.
+pub fn rotate(path: &Path) -> io::Result<Keypair> {
+    let keypair = Keypair::generate_ed25519();
+    save(path, &keypair)?;
+    Ok(keypair)
+}
+
+// Write the keypair atomically (temp file + rename) so a crash never leaves a truncated key.
+fn save(path: &Path, keypair: &Keypair) -> io::Result<()> {
+    let bytes = keypair
+        .to_protobuf_encoding()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let tmp_path = path.with_extension("key.tmp");
+    {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600); // Private key: owner read/write only
+        }
+        let mut file = options.open(&tmp_path)?;
+        file.write_all(&bytes)?;
+        file.sync_all()?;
+    }
+    fs::rename(&tmp_path, path)
+}
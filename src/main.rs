@@ -1,136 +1,357 @@
 mod app;
+mod cli;
+mod codec;
+mod config;
+mod e2e;
 mod geo;
+mod geo_db;
 mod globe;
+mod history;
+mod keystore;
+mod message_id;
 mod network;
+mod profile;
 mod proto;
+mod signing;
+mod store;
 mod tui;
 mod ui;
 
 use app::App;
+use clap::Parser;
+use cli::{Cli, Command, ConfigAction, GeoAction, IdentityAction};
+use config::Config;
 use network::{NetworkCommand, NetworkEvent};
-use std::env;
+use proto::messages::network_message::MessageType;
 use std::io;
+use std::path::Path;
+use std::process::ExitCode;
 use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
+use tracing_subscriber::EnvFilter;
+
+const PROFILE_INTERVAL: Duration = Duration::from_secs(60);
 
 #[tokio::main]
-async fn main() -> io::Result<()> {
-    let args: Vec<String> = env::args().collect();
-    let mut listen_addr = None;
-    let mut dial_addr = None;
-
-    if args.len() >= 3 {
-        match args[1].as_str() {
-            "listen" => {
-                listen_addr = Some(
-                    args[2]
-                        .parse::<libp2p::Multiaddr>()
-                        .expect("Invalid Multiaddr"),
-                );
-            }
-            "dial" => {
-                dial_addr = Some(
-                    args[2]
-                        .parse::<libp2p::Multiaddr>()
-                        .expect("Invalid Multiaddr"),
-                );
-            }
-            _ => {
-                println!("Usage: {} [listen|dial] <multiaddr>", args[0]);
-                return Ok(());
-            }
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("terra-link: {}", e);
+            ExitCode::FAILURE
         }
-    } else if args.len() == 2 {
-        println!("Usage: {} [listen|dial] <multiaddr>", args[0]);
-        return Ok(());
+    }
+}
+
+async fn run(cli: Cli) -> io::Result<()> {
+    let identity_path = cli
+        .identity
+        .clone()
+        .unwrap_or_else(|| keystore::default_identity_path("node"));
+
+    if let Some(Command::Identity { action }) = cli.command {
+        return run_identity_command(action.unwrap_or(IdentityAction::Show), &identity_path);
     }
 
+    // .env feeds the environment layer of the config
     let _ = dotenvy::dotenv();
 
-    ensure_geolite_db().await?;
+    let mut config = Config::load(cli.config.as_deref()).map_err(io::Error::other)?;
+    config.apply_cli(&cli);
+    config.validate().map_err(io::Error::other)?;
 
-    let mut terminal = tui::init()?;
-    let mut app = App::new();
+    if let Some(Command::Config {
+        action: ConfigAction::Show,
+    }) = cli.command
+    {
+        print!("{}", config.to_toml().map_err(io::Error::other)?);
+        return Ok(());
+    }
+    if let Some(Command::Geo {
+        action: GeoAction::Update { allow_unverified },
+    }) = cli.command
+    {
+        return geo_db::update(&config.geo, allow_unverified).await;
+    }
 
-    let (cmd_sender, cmd_receiver) = mpsc::channel(32);
-    let (event_sender, mut event_receiver) = mpsc::channel(32);
+    init_logging(cli.log_level.as_deref(), cli.no_tui)?;
 
-    let local_peer_id = network::start_network(cmd_receiver, event_sender)
-        .await
-        .expect("Failed to start network");
+    let keypair = keystore::load_or_create(&identity_path)?;
 
-    app.local_peer_id = Some(local_peer_id);
+    let geo_resolver = geo::GeoResolver::from_config(&config.geo).map_err(io::Error::other)?;
+    let geo_notice = geo_resolver.notice().map(str::to_string);
+    let mut app = App::new(geo_resolver);
+    app.theme = config.theme.to_theme().map_err(io::Error::other)?;
+    app.twilight = config.globe.twilight;
+    app.globe_render = config.globe.render;
+    app.projection = config.globe.projection;
+    app.mouse_capture = config.globe.mouse;
+    app.set_room_buffer(config.chat.room_buffer);
+    app.set_location(&config.location);
+    app.set_time_format(&config.chat.time_format)
+        .map_err(io::Error::other)?;
+    // Geo is optional: without the database peers still get a rough place on the globe
+    if let Some(notice) = geo_notice {
+        app.push_system(notice);
+    }
+    // Without the store the app still works, just with no scrollback across restarts
+    match store::MessageStore::open(keystore::data_dir().join("messages"), &config.retention()) {
+        Ok(store) => app.attach_store(store),
+        Err(e) => eprintln!("Message history disabled: {}", e),
+    }
 
-    if let Some(ref addr) = listen_addr {
-        cmd_sender
-            .send(NetworkCommand::Listen(addr.clone()))
-            .await
-            .expect("Failed to send listen command: network thread died");
-    }
-    if let Some(ref addr) = dial_addr {
-        // Listen on random port first
-        cmd_sender
-            .send(NetworkCommand::Listen(
-                "/ip4/0.0.0.0/tcp/0".parse().unwrap(),
-            ))
-            .await
-            .expect("Failed to initialize random listen port");
-        cmd_sender
-            .send(NetworkCommand::Dial(addr.clone()))
+    let relay_addr = config.network.relay_addr().map_err(io::Error::other)?;
+
+    let (mut cmd_sender, cmd_receiver) = mpsc::channel(32);
+    let (event_sender, mut event_receiver) = mpsc::channel(32);
+
+    let local_peer_id =
+        network::start_network(keypair, config.network.mdns, cmd_receiver, event_sender)
             .await
-            .expect("Failed to send dial command: network thread died");
-    }
-
-    // If RELAY_NODE is defined in .env, automatically dial it!
-    if let Ok(relay_str) = std::env::var("RELAY_NODE") {
-        if let Ok(relay_addr) = relay_str.parse::<libp2p::Multiaddr>() {
-            // Need a listen port open to perform NAT hole punching
-            if listen_addr.is_none() && dial_addr.is_none() {
-                cmd_sender
-                    .send(NetworkCommand::Listen(
-                        "/ip4/0.0.0.0/tcp/0".parse().unwrap(),
-                    ))
-                    .await
-                    .expect("Failed to initialize random listen port for relay");
-            }
+            .map_err(|e| io::Error::other(format!("Failed to start network: {}", e)))?;
 
-            // libp2p queuing , dispatch ListenOnRelay immediately
-            cmd_sender
-                .send(NetworkCommand::Dial(relay_addr.clone()))
-                .await
-                .expect("Failed to dial relay node");
-
-            //  must wait for the Identify protocol to complete before reserving the circuit!
-            // If send ListenOnRelay immediately, libp2p may try to open the circuit stream
-            let cmd_sender_clone = cmd_sender.clone();
-            tokio::spawn(async move {
-                // Give the connection and Identify exchange 2 seconds to complete
-                tokio::time::sleep(Duration::from_secs(2)).await;
-                let _ = cmd_sender_clone
-                    .send(NetworkCommand::ListenOnRelay(relay_addr))
-                    .await;
-            });
+    app.set_local_peer(local_peer_id);
+
+    let mut listen_addrs = config.network.listen_addrs().map_err(io::Error::other)?;
+    let dial_addrs = config.network.dial_addrs().map_err(io::Error::other)?;
+    // Dialing out or reserving a relay circuit still needs a listen port for NAT hole punching
+    if listen_addrs.is_empty() && (!dial_addrs.is_empty() || relay_addr.is_some()) {
+        listen_addrs.push("/ip4/0.0.0.0/tcp/0".parse().unwrap());
+    }
+    for addr in listen_addrs {
+        send(&cmd_sender, NetworkCommand::Listen(addr)).await?;
+    }
+    for addr in dial_addrs {
+        send(&cmd_sender, NetworkCommand::Dial(addr)).await?;
+    }
+
+    if let Some(relay_addr) = relay_addr.clone() {
+        // libp2p queuing , dispatch ListenOnRelay immediately
+        send(&cmd_sender, NetworkCommand::Dial(relay_addr.clone())).await?;
+
+        //  must wait for the Identify protocol to complete before reserving the circuit!
+        // If send ListenOnRelay immediately, libp2p may try to open the circuit stream
+        let cmd_sender_clone = cmd_sender.clone();
+        tokio::spawn(async move {
+            // Give the connection and Identify exchange 2 seconds to complete
+            tokio::time::sleep(Duration::from_secs(2)).await;
+            let _ = cmd_sender_clone
+                .send(NetworkCommand::ListenOnRelay(relay_addr))
+                .await;
+        });
+    }
+
+    // Seed the DHT from the configured bootstrap nodes plus the relay, which serves it too
+    let mut bootstrap_addrs = config.network.bootstrap_addrs().map_err(io::Error::other)?;
+    bootstrap_addrs.extend(relay_addr);
+    if !bootstrap_addrs.is_empty() {
+        send(&cmd_sender, NetworkCommand::Bootstrap(bootstrap_addrs)).await?;
+    }
+
+    if let Some(nickname) = &config.chat.nickname {
+        app.finish_boot(nickname, &mut cmd_sender);
+    }
+    for room in &config.chat.rooms {
+        app.join_room(room, &mut cmd_sender);
+    }
+
+    if cli.no_tui {
+        // Nobody is there to answer the nickname prompt
+        if !app.boot_complete {
+            app.finish_boot("", &mut cmd_sender);
         }
+        return run_headless(&mut app, &config, &mut event_receiver, cmd_sender).await;
     }
 
-    let res = run_app(&mut terminal, &mut app, &mut event_receiver, cmd_sender).await;
+    let mut terminal = tui::init()?;
+
+    let res = run_app(
+        &mut terminal,
+        &mut app,
+        &config,
+        &mut event_receiver,
+        cmd_sender,
+    )
+    .await;
 
     tui::restore()?;
     res
 }
 
+async fn send(
+    cmd_sender: &mpsc::Sender<NetworkCommand>,
+    command: NetworkCommand,
+) -> io::Result<()> {
+    cmd_sender
+        .send(command)
+        .await
+        .map_err(|_| io::Error::other("Network thread died"))
+}
+
+// Under the TUI, logs go to debug.log so they don't tear up the screen.
+fn init_logging(level: Option<&str>, headless: bool) -> io::Result<()> {
+    let filter = match level {
+        Some(level) => EnvFilter::try_new(level)
+            .map_err(|e| io::Error::other(format!("Invalid --log-level {:?}: {}", level, e)))?,
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn")),
+    };
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    if headless {
+        builder.with_writer(io::stderr).init();
+    } else {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open("debug.log")?;
+        builder
+            .with_ansi(false)
+            .with_writer(std::sync::Mutex::new(file))
+            .init();
+    }
+    Ok(())
+}
+
+// `identity show` prints the PeerId for the stored keypair, `identity rotate` replaces it.
+fn run_identity_command(action: IdentityAction, identity_path: &Path) -> io::Result<()> {
+    let keypair = match action {
+        IdentityAction::Show => keystore::load_or_create(identity_path)?,
+        IdentityAction::Rotate => {
+            let keypair = keystore::rotate(identity_path)?;
+            println!("Generated a new identity. Peers will no longer recognise the old PeerId.");
+            keypair
+        }
+    };
+    println!("Identity file: {}", identity_path.display());
+    println!("Peer ID: {}", keypair.public().to_peer_id());
+    Ok(())
+}
+
+fn broadcast_presence(app: &App, cmd_sender: &mpsc::Sender<NetworkCommand>) {
+    if let Some(me) = app.local_peer_id {
+        let addrs: Vec<String> = app.listen_addrs.iter().map(|a| a.to_string()).collect();
+        if !addrs.is_empty() {
+            let (geohash, location_hidden) = app.presence_location();
+            let _ = cmd_sender.try_send(NetworkCommand::BroadcastPresence {
+                sender_id: me.to_string(),
+                listen_addrs: addrs,
+                geohash,
+                location_hidden,
+            });
+        }
+    }
+}
+
+// Shared by the TUI and headless loops: log the event, react to it, then update the app.
+fn on_network_event(app: &mut App, cmd_sender: &mpsc::Sender<NetworkCommand>, event: NetworkEvent) {
+    match &event {
+        NetworkEvent::PeerConnected(peer, ip, relayed) => {
+            let via = if *relayed { " (relayed)" } else { "" };
+            println!("[CONNECTED] Peer: {} | IP: {}{}", peer, ip, via);
+            // Late joiner catch-up: ask each new neighbour (relay included) for what we missed
+            if !app.peers.contains(peer) {
+                for room in &app.rooms {
+                    let _ = cmd_sender.try_send(NetworkCommand::RequestHistory {
+                        peer: *peer,
+                        room: room.name.clone(),
+                        // Overlap by a minute to absorb clock skew; duplicates are dropped by ID
+                        since: room.latest_timestamp().saturating_sub(60_000),
+                    });
+                }
+            }
+        }
+        NetworkEvent::PeerDisconnected(peer) => {
+            println!("[DISCONNECTED] Peer: {}", peer);
+        }
+        NetworkEvent::Listening(addr) => {
+            println!("[LISTENING] {}", addr);
+        }
+        NetworkEvent::PeerDiscovered(peer, addrs) => {
+            // Try to autodial the discovered peer if we aren't connected!
+            if let Ok(peer_id) = peer.parse::<libp2p::PeerId>() {
+                if !app.peers.contains(&peer_id)
+                    && Some(peer_id) != app.local_peer_id
+                    && !app.dialing_peers.contains(&peer_id)
+                {
+                    let _ = cmd_sender.try_send(NetworkCommand::DialPeer(peer_id, addrs.clone()));
+                    // App handles inserting into dialing_peers in handle_network_event!
+                }
+            }
+        }
+        NetworkEvent::DialError(peer_id) => {
+            println!("[DIAL ERROR] Failed to dial peer: {}", peer_id);
+        }
+        NetworkEvent::Error(msg) => {
+            eprintln!("[ERROR] {}", msg);
+        }
+        _ => {}
+    }
+
+    match event {
+        NetworkEvent::RoomKeyDerived { label, key } => match key {
+            Ok(key) => app.enter_room(key.topic(), &label, Some(key), cmd_sender),
+            Err(e) => app.push_system(e),
+        },
+        event => app.handle_network_event(event),
+    }
+}
+
+// --no-tui: keep the node on the network and print what arrives, until Ctrl-C.
+async fn run_headless(
+    app: &mut App,
+    config: &Config,
+    event_receiver: &mut mpsc::Receiver<NetworkEvent>,
+    cmd_sender: mpsc::Sender<NetworkCommand>,
+) -> io::Result<()> {
+    let mut presence = tokio::time::interval(config.network.presence_interval());
+    let mut profile = tokio::time::interval(PROFILE_INTERVAL);
+    loop {
+        tokio::select! {
+            event = event_receiver.recv() => {
+                let Some(event) = event else {
+                    return Err(io::Error::other("Network thread died"));
+                };
+                match &event {
+                    NetworkEvent::MessageReceived { room, payload } => {
+                        if let MessageType::Chat(chat) = &payload.content {
+                            println!("[{}] {}: {}", room, chat.sender_id, chat.text);
+                        }
+                    }
+                    NetworkEvent::DirectMessageReceived { sender_id, text, .. } => {
+                        println!("[DM] {}: {}", sender_id, text);
+                    }
+                    _ => {}
+                }
+                on_network_event(app, &cmd_sender, event);
+            }
+            _ = presence.tick() => broadcast_presence(app, &cmd_sender),
+            _ = profile.tick() => app.publish_profile(&cmd_sender),
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+        }
+    }
+}
+
 async fn run_app(
     terminal: &mut tui::Tui,
     app: &mut App,
+    config: &Config,
     event_receiver: &mut mpsc::Receiver<NetworkEvent>,
     mut cmd_sender: mpsc::Sender<NetworkCommand>,
 ) -> io::Result<()> {
     let tick_rate = Duration::from_millis(100);
     let mut last_tick = Instant::now();
     let mut last_presence_broadcast = Instant::now();
+    let mut last_profile_broadcast = Instant::now();
+    let presence_interval = config.network.presence_interval();
     let mut needs_render = true;
+    let mut mouse_capture = None;
 
     while !app.should_quit {
+        if mouse_capture != Some(app.mouse_capture) {
+            tui::set_mouse_capture(app.mouse_capture)?;
+            mouse_capture = Some(app.mouse_capture);
+        }
+
         if needs_render {
             terminal.draw(|f| ui::render(f, app))?;
             needs_render = false;
@@ -146,54 +367,20 @@ async fn run_app(
         }
 
         // Periodically broadcast presence if we have local addresses
-        if last_presence_broadcast.elapsed() > Duration::from_secs(15) {
-            if let Some(me) = app.local_peer_id {
-                let addrs: Vec<String> = app.listen_addrs.iter().map(|a| a.to_string()).collect();
-                if !addrs.is_empty() {
-                    let _ = cmd_sender.try_send(NetworkCommand::BroadcastPresence {
-                        sender_id: me.to_string(),
-                        listen_addrs: addrs,
-                    });
-                }
-            }
+        if last_presence_broadcast.elapsed() > presence_interval {
+            broadcast_presence(app, &cmd_sender);
             last_presence_broadcast = Instant::now();
         }
 
+        // Republish our profile now and then so peers that joined since get it
+        if app.boot_complete && last_profile_broadcast.elapsed() > PROFILE_INTERVAL {
+            app.publish_profile(&cmd_sender);
+            last_profile_broadcast = Instant::now();
+        }
+
         // Process network events non-blocking
         while let Ok(event) = event_receiver.try_recv() {
-            match &event {
-                NetworkEvent::PeerConnected(peer, ip) => {
-                    println!("[CONNECTED] Peer: {} | IP: {}", peer, ip);
-                }
-                NetworkEvent::PeerDisconnected(peer) => {
-                    println!("[DISCONNECTED] Peer: {}", peer);
-                }
-                NetworkEvent::Listening(addr) => {
-                    println!("[LISTENING] {}", addr);
-                }
-                NetworkEvent::PeerDiscovered(peer, addrs) => {
-                    // Try to autodial the discovered peer if we aren't connected!
-                    if let Ok(peer_id) = peer.parse::<libp2p::PeerId>() {
-                        if !app.peers.contains(&peer_id)
-                            && Some(peer_id) != app.local_peer_id
-                            && !app.dialing_peers.contains(&peer_id)
-                        {
-                            let _ = cmd_sender
-                                .try_send(NetworkCommand::DialPeer(peer_id, addrs.clone()));
-                            // App handles inserting into dialing_peers in handle_network_event!
-                        }
-                    }
-                }
-                NetworkEvent::DialError(peer_id) => {
-                    println!("[DIAL ERROR] Failed to dial peer: {}", peer_id);
-                }
-                NetworkEvent::Error(msg) => {
-                    eprintln!("[ERROR] {}", msg);
-                }
-                _ => {}
-            }
-
-            app.handle_network_event(event);
+            on_network_event(app, &cmd_sender, event);
             needs_render = true;
         }
 
@@ -210,25 +397,3 @@ async fn run_app(
     }
     Ok(())
 }
-
-async fn ensure_geolite_db() -> io::Result<()> {
-    let db_path = "GeoLite2-City.mmdb";
-    if !std::path::Path::new(db_path).exists() {
-        println!("GeoLite2-City database not found. Downloading (60MB+)...");
-        let url = "https://github.com/P3TERX/GeoLite.mmdb/raw/download/GeoLite2-City.mmdb";
-
-        let mut response = reqwest::get(url)
-            .await
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("Download failed: {}", e)))?;
-
-        let mut file = std::fs::File::create(db_path)?;
-        while let Some(chunk) = response.chunk().await.map_err(|e| {
-            io::Error::new(io::ErrorKind::Other, format!("Failed to read chunk: {}", e))
-        })? {
-            use std::io::Write;
-            file.write_all(&chunk)?;
-        }
-        println!("Download complete!");
-    }
-    Ok(())
-}
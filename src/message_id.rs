@@ -0,0 +1,51 @@
+use crate::proto::messages::MessageIdOnly;
+use libp2p::gossipsub;
+use prost::Message;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+// Fresh ID for a direct message or local notice. Room messages instead carry an ID derived
+// from what they sign (see `signing::message_id`), which receivers check.
+//
+// Hashing in a random salt means two identical messages (same sender, text and millisecond)
+// still get distinct IDs, and the hash is stable across Rust versions and platforms.
+pub fn new_message_id(sender: &str, timestamp: u64, content: &str) -> String {
+    let mut salt = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut salt);
+    let digest = Sha256::new()
+        .chain_update(sender.as_bytes())
+        .chain_update([0])
+        .chain_update(timestamp.to_be_bytes())
+        .chain_update(salt)
+        .chain_update(content.as_bytes())
+        .finalize();
+    to_hex(&digest[..16])
+}
+
+// Gossipsub `message_id_fn`, shared by peers and the relay so both dedupe identically.
+//
+// Uses the payload's `message_id` scoped to the publishing peer, so a peer can't get someone
+// else's message dropped by gossipsub by reusing its ID. Whether the ID is really theirs is
+// checked when the payload is opened. Payloads without one (e.g. from older clients) fall
+// back to a hash of the bytes.
+pub fn gossip_message_id(message: &gossipsub::Message) -> gossipsub::MessageId {
+    let source = message.source.map(|p| p.to_string()).unwrap_or_default();
+    let id = MessageIdOnly::decode(message.data.as_slice())
+        .map(|m| m.message_id)
+        .unwrap_or_default();
+    let id = if id.is_empty() {
+        payload_id(&message.data)
+    } else {
+        id
+    };
+    gossipsub::MessageId::from(format!("{}/{}", source, id))
+}
+
+// Deterministic stand-in ID for payloads that arrive without a `message_id`.
+pub fn payload_id(data: &[u8]) -> String {
+    to_hex(&Sha256::digest(data)[..16])
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -1,16 +1,42 @@
+use crate::codec::ProtobufCodec;
+use crate::e2e::{DmCrypto, RoomKey};
+use crate::history::HistoryStore;
+use crate::proto::messages::{
+    network_message::MessageType, DirectMessage, DirectMessageAck, GlobalChat, HistoryRequest,
+    NetworkMessage, Profile, SealedDirectMessage, SealedRoomMessage,
+};
 use futures::StreamExt;
 use libp2p::{
-    gossipsub, identify, identity, kad, noise,
+    gossipsub, identify, identity, kad, noise, request_response,
     swarm::{NetworkBehaviour, SwarmEvent},
     tcp, yamux, Multiaddr, PeerId, StreamProtocol, SwarmBuilder,
 };
-use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
 use std::error::Error;
-use std::hash::{Hash, Hasher};
 use std::io;
 use std::time::Duration;
 use tokio::sync::mpsc;
 
+// Everyone is subscribed to this room; presence broadcasts go here too.
+pub const DEFAULT_ROOM: &str = "/world";
+
+const DIRECT_MESSAGE_PROTOCOL: &str = "/terra-link/dm/2.0.0";
+
+// Our own DHT, kept apart from the public IPFS one. The relay speaks it too.
+const KAD_PROTOCOL: &str = "/terra-link/kad/1.0.0";
+
+// How often we look up a random key to find peers we haven't heard of yet.
+const RANDOM_WALK_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+// Only built with the `mdns` feature, and even then only switched on by `--mdns`.
+#[cfg(feature = "mdns")]
+type MdnsBehaviour = libp2p::swarm::behaviour::toggle::Toggle<libp2p::mdns::tokio::Behaviour>;
+#[cfg(not(feature = "mdns"))]
+type MdnsBehaviour = libp2p::swarm::dummy::Behaviour;
+
+// How many messages a late joiner asks each peer for, per room.
+const HISTORY_REQUEST_LIMIT: u32 = 50;
+
 #[derive(NetworkBehaviour)]
 pub struct AppBehaviour {
     pub gossipsub: gossipsub::Behaviour,
@@ -20,6 +46,10 @@ pub struct AppBehaviour {
     pub dcutr: libp2p::dcutr::Behaviour,
     pub autonat: libp2p::autonat::Behaviour,
     pub ping: libp2p::ping::Behaviour,
+    pub direct_message:
+        request_response::Behaviour<ProtobufCodec<SealedDirectMessage, DirectMessageAck>>,
+    pub history: crate::history::HistoryBehaviour,
+    pub mdns: MdnsBehaviour,
 }
 
 #[derive(Debug)]
@@ -28,33 +58,234 @@ pub enum NetworkCommand {
     Dial(Multiaddr),
     DialPeer(PeerId, Vec<Multiaddr>),
     ListenOnRelay(Multiaddr),
+    // Seed the DHT with known nodes (each address must end in /p2p/<peer id>) and bootstrap
+    Bootstrap(Vec<Multiaddr>),
+    // `key` is set for passphrase-protected rooms, whose traffic is encrypted with it
+    JoinRoom {
+        room: String,
+        key: Option<RoomKey>,
+    },
+    LeaveRoom(String),
+    // Stretch a private room's passphrase off the event loop; answered with RoomKeyDerived
+    DeriveRoomKey {
+        label: String,
+        passphrase: String,
+    },
     PublishMessage {
+        room: String,
         sender_id: String,
         text: String,
+        timestamp: u64,
+        reply_to: Option<String>,
+    },
+    // A Reaction, MessageEdit or MessageRetraction on an earlier message in `room`
+    PublishAction {
+        room: String,
+        action: MessageType,
+    },
+    // Ask `peer` for room messages it received after `since` (unix millis)
+    RequestHistory {
+        peer: PeerId,
+        room: String,
+        since: u64,
     },
     BroadcastPresence {
         sender_id: String,
         listen_addrs: Vec<String>,
+        geohash: String,
+        location_hidden: bool,
+    },
+    PublishProfile(Profile),
+    SendDirectMessage {
+        receiver: PeerId,
+        message_id: String,
+        sender_id: String,
+        text: String,
     },
 }
 
 #[derive(Debug)]
 pub enum NetworkEvent {
     Listening(Multiaddr),
-    PeerConnected(PeerId, std::net::IpAddr),
+    // The flag is set when every connection to the peer runs through a relay circuit
+    PeerConnected(PeerId, std::net::IpAddr, bool),
+    // The last connection closed
     PeerDisconnected(PeerId),
-    MessageReceived { sender_id: String, text: String },
+    // Still connected, but whether only via relays changed as connections came and went
+    PeerRelayed(PeerId, bool),
+    MessageReceived {
+        room: String,
+        payload: RoomPayload,
+    },
+    HistoryReceived {
+        room: String,
+        payloads: Vec<RoomPayload>,
+    },
+    // `peer` is the authenticated remote; `sender_id` is only their self-declared display name
+    DirectMessageReceived {
+        peer: PeerId,
+        message_id: String,
+        sender_id: String,
+        text: String,
+        timestamp: u64,
+    },
     PeerDiscovered(String, Vec<Multiaddr>),
+    // From a presence broadcast; only sent when the peer declared something
+    LocationDeclared {
+        peer: PeerId,
+        geohash: String,
+        hidden: bool,
+    },
+    // Our own address as a remote peer sees it
+    ObservedAddr(std::net::IpAddr),
+    // Signed by and received from `PeerId` itself
+    ProfileReceived(PeerId, Profile),
+    RoomKeyDerived {
+        label: String,
+        key: Result<RoomKey, String>,
+    },
     DialError(PeerId),
     Error(String),
 }
 
+// A decoded room message (chat line, presence or an action on an earlier message) whose
+// signature, if it has one, checked out.
+#[derive(Debug)]
+pub struct RoomPayload {
+    pub message_id: String,
+    // Signer, or for live gossip the authenticated publisher. None only for unsigned history.
+    pub author: Option<PeerId>,
+    pub content: MessageType,
+}
+
+// Decode a room payload from gossip or history, decrypting it first if the room is private.
+fn open_payload(
+    room_keys: &HashMap<String, RoomKey>,
+    room: &str,
+    data: &[u8],
+) -> Result<Option<RoomPayload>, String> {
+    use prost::Message;
+    // Private rooms wrap the NetworkMessage in a SealedRoomMessage only key holders can open
+    let plaintext = match room_keys.get(room) {
+        Some(key) => SealedRoomMessage::decode(data)
+            .map_err(|e| e.to_string())
+            .and_then(|sealed| key.open(&sealed))?,
+        None => data.to_vec(),
+    };
+    let Ok(mut msg) = NetworkMessage::decode(plaintext.as_slice()) else {
+        return Ok(None);
+    };
+    let author = crate::signing::verify(room, &msg)?;
+    match author {
+        Some(author) => {
+            if crate::signing::message_id(room, &msg).as_ref() != Some(&msg.message_id) {
+                return Err(format!("Rejected message from {} with a forged ID", author));
+            }
+        }
+        // Unsigned payloads can't prove their ID is theirs, so they get one from their bytes
+        None => msg.message_id = crate::message_id::payload_id(&plaintext),
+    }
+    Ok(msg.message_type.map(|content| RoomPayload {
+        message_id: msg.message_id,
+        author,
+        content,
+    }))
+}
+
+// Whether a history response for `room` answers the request `id` asked; the request is
+// forgotten either way, as only one response comes back.
+fn answers_request<K: std::hash::Hash + Eq>(
+    requests: &mut HashMap<K, String>,
+    id: &K,
+    room: &str,
+) -> bool {
+    requests
+        .remove(id)
+        .is_some_and(|requested| requested == room)
+}
+
+// Live gossip is attributed to the peer gossipsub authenticated as its publisher. Payloads
+// whose own signature or presence claim names anyone else are rejected.
+fn bind_to_source(
+    payload: Option<RoomPayload>,
+    source: Option<PeerId>,
+) -> Result<Option<RoomPayload>, String> {
+    let Some(mut payload) = payload else {
+        return Ok(None);
+    };
+    let source = source.ok_or_else(|| "Rejected gossip without a publisher".to_string())?;
+    if let Some(author) = payload.author.filter(|author| *author != source) {
+        return Err(format!(
+            "Rejected message signed by {} but published by {}",
+            author, source
+        ));
+    }
+    if let MessageType::Presence(presence) = &payload.content {
+        if presence.sender_id != source.to_string() {
+            return Err(format!(
+                "Rejected presence from {} claiming to be {}",
+                source, presence.sender_id
+            ));
+        }
+    }
+    payload.author = Some(source);
+    Ok(Some(payload))
+}
+
+// Sign, seal (for private rooms) and publish a room message, keeping a copy for history.
+fn publish_room_message(
+    swarm: &mut libp2p::Swarm<AppBehaviour>,
+    signing_key: &identity::Keypair,
+    room_keys: &HashMap<String, RoomKey>,
+    history: &mut HistoryStore,
+    room: &str,
+    mut msg: NetworkMessage,
+) -> Result<(), String> {
+    use prost::Message;
+    crate::signing::sign(signing_key, room, &mut msg)?;
+    let buf = msg.encode_to_vec();
+
+    let payload = match room_keys.get(room) {
+        Some(key) => {
+            let sealed = key.seal(&buf)?;
+            SealedRoomMessage {
+                message_id: msg.message_id,
+                ..sealed
+            }
+            .encode_to_vec()
+        }
+        None => buf,
+    };
+
+    // Gossipsub never echoes our own messages, so keep them for history here
+    history.record(room, &payload);
+    match swarm
+        .behaviour_mut()
+        .gossipsub
+        .publish(gossipsub::IdentTopic::new(room), payload)
+    {
+        // Nobody else is in the room yet; they'll pick it up through history when they join
+        Ok(_) | Err(gossipsub::PublishError::NoPeersSubscribedToTopic) => Ok(()),
+        Err(e) => Err(format!("Publish error: {:?}", e)),
+    }
+}
+
 pub async fn start_network(
+    local_key: identity::Keypair,
+    enable_mdns: bool,
     mut cmd_receiver: mpsc::Receiver<NetworkCommand>,
     event_sender: mpsc::Sender<NetworkEvent>,
 ) -> Result<PeerId, Box<dyn Error>> {
-    let local_key = identity::Keypair::generate_ed25519();
     let local_peer_id = PeerId::from(local_key.public());
+    let signing_key = local_key.clone();
+    let mut dm_crypto = DmCrypto::new(&local_key)?;
+    let mut room_keys: HashMap<String, RoomKey> = HashMap::new();
+    let mut history = HistoryStore::default();
+    // Open connections per peer, and whether each is a relay circuit
+    let mut connections: HashMap<PeerId, HashMap<libp2p::swarm::ConnectionId, bool>> =
+        HashMap::new();
+    // The room each history request asked about; a response may only fill that room
+    let mut history_requests: HashMap<request_response::OutboundRequestId, String> = HashMap::new();
 
     // Setup swarm
     let mut swarm = SwarmBuilder::with_existing_identity(local_key)
@@ -68,31 +299,25 @@ pub async fn start_network(
         .with_relay_client(noise::Config::new, yamux::Config::default)?
         .with_behaviour(|key, relay_client| {
             // Setup Gossipsub config
-            let message_id_fn = |message: &gossipsub::Message| {
-                let mut s = DefaultHasher::new();
-                message.data.hash(&mut s);
-                gossipsub::MessageId::from(s.finish().to_string())
-            };
-
             let gossipsub_config = gossipsub::ConfigBuilder::default()
                 .heartbeat_interval(Duration::from_secs(1))
                 .validation_mode(gossipsub::ValidationMode::Strict)
-                .message_id_fn(message_id_fn)
+                .message_id_fn(crate::message_id::gossip_message_id)
                 .build()
-                .map_err(|msg| io::Error::new(io::ErrorKind::Other, msg))?; // Map config builder error
+                .map_err(io::Error::other)?; // Map config builder error
 
             let gossipsub = gossipsub::Behaviour::new(
                 gossipsub::MessageAuthenticity::Signed(key.clone()),
                 gossipsub_config,
             )
-            .map_err(|msg| io::Error::new(io::ErrorKind::Other, msg))?;
+            .map_err(io::Error::other)?;
 
             let identify = identify::Behaviour::new(identify::Config::new(
                 "/terra-link/0.1.0".into(),
                 key.public(),
             ));
 
-            let kad_config = kad::Config::new(StreamProtocol::new("/terra-link/kad/1.0.0"));
+            let kad_config = kad::Config::new(StreamProtocol::new(KAD_PROTOCOL));
             let store = kad::store::MemoryStore::new(local_peer_id);
             let kademlia = kad::Behaviour::with_config(local_peer_id, store, kad_config);
 
@@ -101,6 +326,32 @@ pub async fn start_network(
 
             let ping = libp2p::ping::Behaviour::default();
 
+            // Direct messages travel point-to-point instead of being broadcast on a topic
+            let direct_message = request_response::Behaviour::new(
+                [(
+                    StreamProtocol::new(DIRECT_MESSAGE_PROTOCOL),
+                    request_response::ProtocolSupport::Full,
+                )],
+                request_response::Config::default(),
+            );
+
+            #[cfg(feature = "mdns")]
+            let mdns = match enable_mdns {
+                true => Some(libp2p::mdns::tokio::Behaviour::new(
+                    libp2p::mdns::Config::default(),
+                    local_peer_id,
+                )?),
+                false => None,
+            }
+            .into();
+            #[cfg(not(feature = "mdns"))]
+            let mdns = {
+                if enable_mdns {
+                    return Err(io::Error::other("built without the mdns feature").into());
+                }
+                libp2p::swarm::dummy::Behaviour
+            };
+
             Ok(AppBehaviour {
                 gossipsub,
                 identify,
@@ -109,78 +360,236 @@ pub async fn start_network(
                 dcutr,
                 autonat,
                 ping,
+                direct_message,
+                history: crate::history::new_behaviour(),
+                mdns,
             })
         })?
         .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(60 * 60)))
         .build();
 
-    let topic = gossipsub::IdentTopic::new("/world");
+    let topic = gossipsub::IdentTopic::new(DEFAULT_ROOM);
     swarm.behaviour_mut().gossipsub.subscribe(&topic)?;
 
     tokio::spawn(async move {
+        // Kademlia re-bootstraps on its own; random walks add peers beyond our own bucket
+        let mut random_walk = tokio::time::interval_at(
+            tokio::time::Instant::now() + RANDOM_WALK_INTERVAL,
+            RANDOM_WALK_INTERVAL,
+        );
         loop {
             tokio::select! {
                 event = swarm.select_next_some() => match event {
                     SwarmEvent::NewListenAddr { address, .. } => {
                         let _ = event_sender.send(NetworkEvent::Listening(address)).await;
                     }
-                    SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
+                    SwarmEvent::ConnectionEstablished { peer_id, connection_id, endpoint, .. } => {
                         let mut ip = None;
+                        let mut relayed = false;
                         for protocol in endpoint.get_remote_address().iter() {
                             match protocol {
                                 libp2p::multiaddr::Protocol::Ip4(ipv4) => ip = Some(std::net::IpAddr::V4(ipv4)),
                                 libp2p::multiaddr::Protocol::Ip6(ipv6) => ip = Some(std::net::IpAddr::V6(ipv6)),
+                                libp2p::multiaddr::Protocol::P2pCircuit => relayed = true,
                                 _ => {}
                             }
                         }
+                        let open = connections.entry(peer_id).or_default();
+                        open.insert(connection_id, relayed);
+                        let relayed = open.values().all(|&relayed| relayed);
                         if let Some(ip) = ip {
-                            let _ = event_sender.send(NetworkEvent::PeerConnected(peer_id, ip)).await;
+                            let _ = event_sender.send(NetworkEvent::PeerConnected(peer_id, ip, relayed)).await;
                         }
                     }
-                    SwarmEvent::ConnectionClosed { peer_id, .. } => {
-                        let _ = event_sender.send(NetworkEvent::PeerDisconnected(peer_id)).await;
+                    SwarmEvent::ConnectionClosed { peer_id, connection_id, num_established, .. } => {
+                        // A relay circuit torn down after hole punching leaves the direct one
+                        if num_established == 0 {
+                            connections.remove(&peer_id);
+                            let _ = event_sender.send(NetworkEvent::PeerDisconnected(peer_id)).await;
+                        } else if let Some(open) = connections.get_mut(&peer_id) {
+                            let was_relayed = open.values().all(|&relayed| relayed);
+                            open.remove(&connection_id);
+                            let relayed = open.values().all(|&relayed| relayed);
+                            if relayed != was_relayed {
+                                let _ = event_sender.send(NetworkEvent::PeerRelayed(peer_id, relayed)).await;
+                            }
+                        }
                     }
                     SwarmEvent::Behaviour(AppBehaviourEvent::Gossipsub(gossipsub::Event::Message {
                         propagation_source: _peer_id,
                         message_id: _id,
                         message,
                     })) => {
-                        use prost::Message;
-                        if let Ok(net_msg) = crate::proto::messages::NetworkMessage::decode(message.data.as_slice()) {
-                            if let Some(msg_type) = net_msg.message_type {
-                                match msg_type {
-                                    crate::proto::messages::network_message::MessageType::Chat(global_chat) => {
-                                        let _ = event_sender.send(NetworkEvent::MessageReceived {
-                                            sender_id: global_chat.sender_id,
-                                            text: global_chat.text,
-                                        }).await;
+                        // IdentTopic hashes are the topic string itself
+                        let room = message.topic.into_string();
+
+                        let opened = open_payload(&room_keys, &room, &message.data)
+                            .and_then(|payload| bind_to_source(payload, message.source));
+                        if let Ok(Some(_)) = opened {
+                            history.record(&room, &message.data);
+                        }
+                        match opened {
+                            Ok(Some(RoomPayload { content: MessageType::Presence(presence), author: Some(peer), .. })) => {
+                                if !presence.geohash.is_empty() || presence.location_hidden {
+                                    let _ = event_sender
+                                        .send(NetworkEvent::LocationDeclared {
+                                            peer,
+                                            geohash: presence.geohash,
+                                            hidden: presence.location_hidden,
+                                        })
+                                        .await;
+                                }
+                                let mut addrs = Vec::new();
+                                for addr_str in presence.listen_addrs {
+                                    if let Ok(addr) = addr_str.parse::<Multiaddr>() {
+                                        addrs.push(addr);
                                     }
-                                    crate::proto::messages::network_message::MessageType::Presence(presence) => {
-                                        let mut addrs = Vec::new();
-                                        for addr_str in presence.listen_addrs {
-                                            if let Ok(addr) = addr_str.parse::<Multiaddr>() {
-                                                addrs.push(addr);
-                                            }
-                                        }
-                                        if !addrs.is_empty() {
-                                            let _ = event_sender.send(NetworkEvent::PeerDiscovered(presence.sender_id, addrs)).await;
-                                        }
+                                }
+                                if !addrs.is_empty() {
+                                    let _ = event_sender.send(NetworkEvent::PeerDiscovered(presence.sender_id, addrs)).await;
+                                }
+                            }
+                            Ok(Some(RoomPayload { content: MessageType::Profile(profile), author: Some(peer), .. })) => {
+                                let _ = event_sender.send(NetworkEvent::ProfileReceived(peer, profile)).await;
+                            }
+                            // DirectMessages arrive over request-response, never via gossip
+                            Ok(Some(RoomPayload { content: MessageType::DirectMessage(_), .. })) | Ok(None) => {}
+                            Ok(Some(payload)) => {
+                                let _ = event_sender.send(NetworkEvent::MessageReceived { room, payload }).await;
+                            }
+                            Err(e) => {
+                                let _ = event_sender.send(NetworkEvent::Error(format!("{} on {}", e, room))).await;
+                            }
+                        }
+                    }
+                    SwarmEvent::Behaviour(AppBehaviourEvent::History(request_response::Event::Message {
+                        message: request_response::Message::Request { request, channel, .. },
+                        ..
+                    })) => {
+                        let response = history.query(&request);
+                        let _ = swarm.behaviour_mut().history.send_response(channel, response);
+                    }
+                    SwarmEvent::Behaviour(AppBehaviourEvent::History(request_response::Event::Message {
+                        peer,
+                        message: request_response::Message::Response { request_id, response },
+                        ..
+                    })) => {
+                        if !answers_request(&mut history_requests, &request_id, &response.room) {
+                            let _ = event_sender.send(NetworkEvent::Error(format!("Ignored history from {} for a room we didn't ask about", peer))).await;
+                            continue;
+                        }
+                        let mut payloads = Vec::new();
+                        let mut failed = 0;
+                        for data in &response.payloads {
+                            match open_payload(&room_keys, &response.room, data) {
+                                // Without gossipsub's signature only our own can say who wrote it
+                                Ok(Some(RoomPayload { author: None, .. })) => failed += 1,
+                                Ok(Some(payload)) => {
+                                    if matches!(payload.content, MessageType::Chat(_) | MessageType::Reaction(_) | MessageType::Edit(_) | MessageType::Retraction(_)) {
+                                        payloads.push(payload);
                                     }
-                                    _ => {} // DirectMessage or other uncaught variants
                                 }
+                                Ok(None) => {}
+                                Err(_) => failed += 1,
+                            }
+                        }
+                        if failed > 0 {
+                            let _ = event_sender.send(NetworkEvent::Error(format!("{} history messages for {} could not be decrypted or verified", failed, response.room))).await;
+                        }
+                        if !payloads.is_empty() {
+                            let _ = event_sender.send(NetworkEvent::HistoryReceived { room: response.room, payloads }).await;
+                        }
+                    }
+                    SwarmEvent::Behaviour(AppBehaviourEvent::DirectMessage(request_response::Event::Message {
+                        peer,
+                        message: request_response::Message::Request { request, channel, .. },
+                        ..
+                    })) => {
+                        let ack = DirectMessageAck { timestamp: request.timestamp };
+                        let _ = swarm.behaviour_mut().direct_message.send_response(channel, ack);
+                        match dm_crypto.open(&peer, &request) {
+                            Ok(dm) => {
+                                let _ = event_sender.send(NetworkEvent::DirectMessageReceived {
+                                    peer,
+                                    message_id: dm.message_id,
+                                    sender_id: dm.sender_id,
+                                    text: dm.text,
+                                    timestamp: dm.timestamp,
+                                }).await;
+                            }
+                            Err(e) => {
+                                let _ = event_sender.send(NetworkEvent::Error(e)).await;
                             }
                         }
                     }
+                    SwarmEvent::Behaviour(AppBehaviourEvent::History(request_response::Event::OutboundFailure { request_id, .. })) => {
+                        history_requests.remove(&request_id);
+                    }
+                    SwarmEvent::Behaviour(AppBehaviourEvent::DirectMessage(request_response::Event::OutboundFailure {
+                        peer,
+                        error,
+                        ..
+                    })) => {
+                        let _ = event_sender.send(NetworkEvent::Error(format!("Direct message to {} failed: {}", peer, error))).await;
+                    }
                     SwarmEvent::OutgoingConnectionError { peer_id: Some(peer_id), .. } => {
                         let _ = event_sender.send(NetworkEvent::DialError(peer_id)).await;
                     }
-                    other => {
-                        use std::io::Write;
-                        if let Ok(mut f) = std::fs::OpenOptions::new().create(true).append(true).open("debug.log") {
-                            let _ = writeln!(f, "Unhandled event: {:?}", other);
+                    SwarmEvent::Behaviour(AppBehaviourEvent::Identify(identify::Event::Received { peer_id, info, .. })) => {
+                        if let Some(ip) = info.observed_addr.iter().find_map(|protocol| match protocol {
+                            libp2p::multiaddr::Protocol::Ip4(ipv4) => Some(std::net::IpAddr::V4(ipv4)),
+                            libp2p::multiaddr::Protocol::Ip6(ipv6) => Some(std::net::IpAddr::V6(ipv6)),
+                            _ => None,
+                        }) {
+                            let _ = event_sender.send(NetworkEvent::ObservedAddr(ip)).await;
+                        }
+                        // Only DHT servers belong in the routing table; clients behind NAT can't answer queries
+                        if info.protocols.iter().any(|p| p.as_ref() == KAD_PROTOCOL) {
+                            for addr in info.listen_addrs {
+                                swarm.behaviour_mut().kademlia.add_address(&peer_id, addr);
+                            }
                         }
                     }
+                    SwarmEvent::Behaviour(AppBehaviourEvent::Kademlia(kad::Event::RoutingUpdated { peer, addresses, is_new_peer: true, .. })) => {
+                        if peer != local_peer_id && !swarm.is_connected(&peer) {
+                            let _ = event_sender.send(NetworkEvent::PeerDiscovered(peer.to_string(), addresses.into_vec())).await;
+                        }
+                    }
+                    SwarmEvent::Behaviour(AppBehaviourEvent::Kademlia(kad::Event::OutboundQueryProgressed {
+                        result: kad::QueryResult::GetClosestPeers(Ok(kad::GetClosestPeersOk { peers, .. })),
+                        ..
+                    })) => {
+                        for peer in peers {
+                            if peer.peer_id != local_peer_id && !swarm.is_connected(&peer.peer_id) && !peer.addrs.is_empty() {
+                                let _ = event_sender.send(NetworkEvent::PeerDiscovered(peer.peer_id.to_string(), peer.addrs)).await;
+                            }
+                        }
+                    }
+                    #[cfg(feature = "mdns")]
+                    SwarmEvent::Behaviour(AppBehaviourEvent::Mdns(libp2p::mdns::Event::Discovered(found))) => {
+                        // One event per peer, with every address it announced on the LAN
+                        let mut by_peer: HashMap<PeerId, Vec<Multiaddr>> = HashMap::new();
+                        for (peer, addr) in found {
+                            by_peer.entry(peer).or_default().push(addr);
+                        }
+                        for (peer, addrs) in by_peer {
+                            if !swarm.is_connected(&peer) {
+                                let _ = event_sender.send(NetworkEvent::PeerDiscovered(peer.to_string(), addrs)).await;
+                            }
+                        }
+                    }
+                    // Open connections notice a departed peer on their own
+                    #[cfg(feature = "mdns")]
+                    SwarmEvent::Behaviour(AppBehaviourEvent::Mdns(libp2p::mdns::Event::Expired(_))) => {}
+                    // Bootstrap progress, timeouts and inbound requests need no action
+                    SwarmEvent::Behaviour(AppBehaviourEvent::Kademlia(_)) => {}
+                    other => {
+                        tracing::debug!("Unhandled event: {:?}", other);
+                    }
                 },
+                _ = random_walk.tick() => {
+                    swarm.behaviour_mut().kademlia.get_closest_peers(PeerId::random());
+                }
                 cmd = cmd_receiver.recv() => {
                     if let Some(command) = cmd {
                         match command {
@@ -207,58 +616,143 @@ pub async fn start_network(
                                     let _ = event_sender.send(NetworkEvent::Error(format!("Relay Reservation error for {}: {}", addr, e))).await;
                                 }
                             }
-                            NetworkCommand::PublishMessage { sender_id, text } => {
+                            NetworkCommand::Bootstrap(addrs) => {
+                                for addr in addrs {
+                                    match addr.iter().last() {
+                                        Some(libp2p::multiaddr::Protocol::P2p(peer_id)) => {
+                                            swarm.behaviour_mut().kademlia.add_address(&peer_id, addr);
+                                        }
+                                        _ => {
+                                            let _ = event_sender.send(NetworkEvent::Error(format!("Bootstrap address {} has no /p2p/<peer id>", addr))).await;
+                                        }
+                                    }
+                                }
+                                // Fails only with an empty routing table, i.e. every address was rejected above
+                                let _ = swarm.behaviour_mut().kademlia.bootstrap();
+                            }
+                            NetworkCommand::JoinRoom { room, key } => {
+                                if let Err(e) = swarm.behaviour_mut().gossipsub.subscribe(&gossipsub::IdentTopic::new(&room)) {
+                                    let _ = event_sender.send(NetworkEvent::Error(format!("Failed to join {}: {:?}", room, e))).await;
+                                } else if let Some(key) = key {
+                                    room_keys.insert(room, key);
+                                }
+                            }
+                            NetworkCommand::DeriveRoomKey { label, passphrase } => {
+                                let event_sender = event_sender.clone();
+                                tokio::spawn(async move {
+                                    let derive_label = label.clone();
+                                    let key = tokio::task::spawn_blocking(move || RoomKey::derive(&derive_label, &passphrase))
+                                        .await
+                                        .unwrap_or_else(|e| Err(format!("Failed to derive room key: {}", e)));
+                                    let _ = event_sender.send(NetworkEvent::RoomKeyDerived { label, key }).await;
+                                });
+                            }
+                            NetworkCommand::LeaveRoom(room) => {
+                                swarm.behaviour_mut().gossipsub.unsubscribe(&gossipsub::IdentTopic::new(&room));
+                                room_keys.remove(&room);
+                            }
+                            NetworkCommand::PublishMessage { room, sender_id, text, timestamp, reply_to } => {
+                                let chat = GlobalChat {
+                                    sender_id,
+                                    text,
+                                    timestamp,
+                                    reply_to: reply_to.unwrap_or_default(),
+                                };
+                                let msg = NetworkMessage {
+                                    message_type: Some(MessageType::Chat(chat)),
+                                    ..Default::default()
+                                };
+                                if let Err(e) = publish_room_message(&mut swarm, &signing_key, &room_keys, &mut history, &room, msg) {
+                                    let _ = event_sender.send(NetworkEvent::Error(e)).await;
+                                }
+                            }
+                            NetworkCommand::PublishAction { room, action } => {
+                                let msg = NetworkMessage {
+                                    message_type: Some(action),
+                                    ..Default::default()
+                                };
+                                if let Err(e) = publish_room_message(&mut swarm, &signing_key, &room_keys, &mut history, &room, msg) {
+                                    let _ = event_sender.send(NetworkEvent::Error(e)).await;
+                                }
+                            }
+                            NetworkCommand::RequestHistory { peer, room, since } => {
+                                let request = HistoryRequest {
+                                    room: room.clone(),
+                                    since,
+                                    limit: HISTORY_REQUEST_LIMIT,
+                                };
+                                let request_id = swarm.behaviour_mut().history.send_request(&peer, request);
+                                history_requests.insert(request_id, room);
+                            }
+                            NetworkCommand::BroadcastPresence { sender_id, listen_addrs, geohash, location_hidden } => {
                                 use prost::Message;
                                 use std::time::{SystemTime, UNIX_EPOCH};
-                                let topic = gossipsub::IdentTopic::new("/world");
+                                let topic = gossipsub::IdentTopic::new(DEFAULT_ROOM);
 
                                 let timestamp = SystemTime::now()
                                     .duration_since(UNIX_EPOCH)
                                     .unwrap_or_default()
                                     .as_millis() as u64;
 
-                                let chat = crate::proto::messages::GlobalChat {
+                                let presence = crate::proto::messages::Presence {
                                     sender_id,
-                                    text,
+                                    listen_addrs,
                                     timestamp,
+                                    geohash,
+                                    location_hidden,
                                 };
 
-                                let msg = crate::proto::messages::NetworkMessage {
-                                    message_type: Some(crate::proto::messages::network_message::MessageType::Chat(chat)),
+                                let mut msg = crate::proto::messages::NetworkMessage {
+                                    message_type: Some(crate::proto::messages::network_message::MessageType::Presence(presence)),
+                                    ..Default::default()
                                 };
+                                if let Err(e) = crate::signing::sign(&signing_key, DEFAULT_ROOM, &mut msg) {
+                                    let _ = event_sender.send(NetworkEvent::Error(e)).await;
+                                    continue;
+                                }
 
                                 let mut buf = Vec::new();
                                 msg.encode(&mut buf).unwrap();
 
-                                if let Err(e) = swarm.behaviour_mut().gossipsub.publish(topic, buf) {
-                                    eprintln!("Publish error: {:?}", e);
+                                match swarm.behaviour_mut().gossipsub.publish(topic, buf) {
+                                    // Nobody to tell yet
+                                    Ok(_) | Err(gossipsub::PublishError::NoPeersSubscribedToTopic) => {}
+                                    Err(e) => tracing::warn!("Broadcast presence error: {:?}", e),
                                 }
                             }
-                            NetworkCommand::BroadcastPresence { sender_id, listen_addrs } => {
-                                use prost::Message;
+                            NetworkCommand::PublishProfile(profile) => {
+                                let msg = NetworkMessage {
+                                    message_type: Some(MessageType::Profile(profile)),
+                                    ..Default::default()
+                                };
+                                if let Err(e) = publish_room_message(&mut swarm, &signing_key, &room_keys, &mut history, DEFAULT_ROOM, msg) {
+                                    let _ = event_sender.send(NetworkEvent::Error(e)).await;
+                                }
+                            }
+                            NetworkCommand::SendDirectMessage { receiver, message_id, sender_id, text } => {
                                 use std::time::{SystemTime, UNIX_EPOCH};
-                                let topic = gossipsub::IdentTopic::new("/world");
 
                                 let timestamp = SystemTime::now()
                                     .duration_since(UNIX_EPOCH)
                                     .unwrap_or_default()
                                     .as_millis() as u64;
 
-                                let presence = crate::proto::messages::Presence {
+                                let dm = DirectMessage {
                                     sender_id,
-                                    listen_addrs,
+                                    receiver_id: receiver.to_string(),
+                                    text,
                                     timestamp,
+                                    message_id,
                                 };
 
-                                let msg = crate::proto::messages::NetworkMessage {
-                                    message_type: Some(crate::proto::messages::network_message::MessageType::Presence(presence)),
-                                };
-
-                                let mut buf = Vec::new();
-                                msg.encode(&mut buf).unwrap();
-
-                                if let Err(e) = swarm.behaviour_mut().gossipsub.publish(topic, buf) {
-                                    eprintln!("Broadcast presence error: {:?}", e);
+                                match dm_crypto.seal(&receiver, &dm) {
+                                    // Opens a stream on an existing connection (direct or relayed), dialing if needed
+                                    Ok(sealed) => {
+                                        swarm.behaviour_mut().direct_message.send_request(&receiver, sealed);
+                                    }
+                                    Err(e) => {
+                                        let _ = event_sender.send(NetworkEvent::Error(e)).await;
+                                    }
                                 }
                             }
                         }
@@ -273,3 +767,60 @@ pub async fn start_network(
 
     Ok(local_peer_id)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chat(text: &str) -> NetworkMessage {
+        NetworkMessage {
+            message_type: Some(MessageType::Chat(GlobalChat {
+                sender_id: "alice".to_string(),
+                text: text.to_string(),
+                timestamp: 1,
+                reply_to: String::new(),
+            })),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn signed_payload_keeps_its_id() {
+        use prost::Message;
+        let keypair = identity::Keypair::generate_ed25519();
+        let mut message = chat("hello");
+        crate::signing::sign(&keypair, "/world", &mut message).unwrap();
+        let payload = open_payload(&HashMap::new(), "/world", &message.encode_to_vec())
+            .unwrap()
+            .unwrap();
+        assert_eq!(payload.message_id, message.message_id);
+        assert_eq!(payload.author, Some(keypair.public().to_peer_id()));
+    }
+
+    #[test]
+    fn unsigned_payload_cannot_claim_an_id() {
+        use prost::Message;
+        let keypair = identity::Keypair::generate_ed25519();
+        let mut original = chat("hello");
+        crate::signing::sign(&keypair, "/world", &mut original).unwrap();
+        let forged = NetworkMessage {
+            message_id: original.message_id.clone(),
+            ..chat("goodbye")
+        };
+        let payload = open_payload(&HashMap::new(), "/world", &forged.encode_to_vec())
+            .unwrap()
+            .unwrap();
+        assert_ne!(payload.message_id, original.message_id);
+    }
+
+    #[test]
+    fn history_response_must_match_requested_room() {
+        let mut requests = HashMap::from([(1, "/world".to_string()), (2, "/dev".to_string())]);
+        assert!(!answers_request(&mut requests, &1, "/dev"));
+        // Already answered, even if wrongly
+        assert!(!answers_request(&mut requests, &1, "/world"));
+        assert!(!answers_request(&mut requests, &3, "/world"));
+        assert!(answers_request(&mut requests, &2, "/dev"));
+        assert!(requests.is_empty());
+    }
+}
@@ -0,0 +1,68 @@
+use crate::proto::messages::Profile;
+use libp2p::PeerId;
+use sha2::{Digest, Sha256};
+
+pub const MAX_NICKNAME_CHARS: usize = 16;
+pub const MAX_STATUS_CHARS: usize = 64;
+
+// Glyph shown for peers whose profile doesn't pick one.
+pub const DEFAULT_AVATAR: &str = "◈";
+
+// Our starting profile before the user customises it; the colour is stable per identity.
+pub fn default_for(peer: &PeerId) -> Profile {
+    Profile {
+        nickname: String::new(),
+        status: String::new(),
+        color: default_color(peer),
+        avatar: DEFAULT_AVATAR.to_string(),
+        updated_at: 0,
+    }
+}
+
+// A bright colour derived from the PeerId, as 0xRRGGBB, so peers are told apart before
+// they choose one.
+pub fn default_color(peer: &PeerId) -> u32 {
+    let digest = Sha256::digest(peer.to_bytes());
+    // Keep every channel above 96 so it reads on the dark HUD background
+    let channel = |b: u8| 96 + (b as u32 % 160);
+    (channel(digest[0]) << 16) | (channel(digest[1]) << 8) | channel(digest[2])
+}
+
+// Parses "#rrggbb" or "rrggbb".
+pub fn parse_color(input: &str) -> Option<u32> {
+    let hex = input.trim().trim_start_matches('#');
+    if hex.len() != 6 {
+        return None;
+    }
+    u32::from_str_radix(hex, 16).ok()
+}
+
+// Rejects profiles other peers couldn't display sensibly. Applied to our own edits and to
+// everything received.
+pub fn validate(profile: &Profile) -> Result<(), String> {
+    if profile.nickname.chars().count() > MAX_NICKNAME_CHARS {
+        return Err(format!(
+            "Nickname is longer than {} characters",
+            MAX_NICKNAME_CHARS
+        ));
+    }
+    if profile.status.chars().count() > MAX_STATUS_CHARS {
+        return Err(format!(
+            "Status is longer than {} characters",
+            MAX_STATUS_CHARS
+        ));
+    }
+    if profile.color > 0xFF_FF_FF {
+        return Err("Colour must be a 24-bit RGB value".to_string());
+    }
+    let mut avatar = profile.avatar.chars();
+    let single = matches!((avatar.next(), avatar.next()), (Some(c), None) if !c.is_control() && !c.is_whitespace());
+    if !single {
+        return Err("Avatar must be a single visible character".to_string());
+    }
+    let text = profile.nickname.chars().chain(profile.status.chars());
+    if text.clone().any(char::is_control) {
+        return Err("Profile text can't contain control characters".to_string());
+    }
+    Ok(())
+}
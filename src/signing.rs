@@ -0,0 +1,208 @@
+use crate::proto::messages::{network_message::MessageType, NetworkMessage};
+use libp2p::{identity, PeerId};
+
+// v2 binds the room name into the signature
+const SIGNING_DOMAIN: &[u8] = b"terra-link/room-message/v2";
+
+// Signs a room message with our identity key, filling in `author_id`, `message_id` and
+// `signature`.
+//
+// Gossipsub already signs what it relays, but that signature is gone once a payload is
+// replayed through history sync or the relay. Signing at this layer lets anyone check who
+// wrote a message, and so whether they may edit or retract it, however it arrived. The
+// room (its topic) is signed too, so a message can't be replayed into another room.
+pub fn sign(
+    keypair: &identity::Keypair,
+    room: &str,
+    message: &mut NetworkMessage,
+) -> Result<(), String> {
+    message.author_id = keypair.public().to_peer_id().to_string();
+    message.message_id =
+        message_id(room, message).ok_or_else(|| "This message type can't be signed".to_string())?;
+    let bytes = signed_bytes(room, message)
+        .ok_or_else(|| "This message type can't be signed".to_string())?;
+    message.signature = keypair
+        .sign(&bytes)
+        .map_err(|e| format!("Failed to sign message: {}", e))?;
+    Ok(())
+}
+
+// The PeerId that signed `message`, or None for unsigned messages from older clients.
+pub fn verify(room: &str, message: &NetworkMessage) -> Result<Option<PeerId>, String> {
+    if message.author_id.is_empty() && message.signature.is_empty() {
+        return Ok(None);
+    }
+    let author: PeerId = message
+        .author_id
+        .parse()
+        .map_err(|_| format!("Malformed author id {:?}", message.author_id))?;
+    let bytes = signed_bytes(room, message)
+        .ok_or_else(|| format!("Unexpected signed message from {}", author))?;
+    if !peer_public_key(&author)?.verify(&bytes, &message.signature) {
+        return Err(format!("Invalid signature on message from {}", author));
+    }
+    Ok(Some(author))
+}
+
+// The ID a signed message must carry: a hash of everything the signature covers bar the ID
+// itself. Since that includes the author, receivers can recompute it, and no one can claim
+// the ID of a message they didn't write.
+pub fn message_id(room: &str, message: &NetworkMessage) -> Option<String> {
+    signed_bytes_with_id(room, "", message).map(|bytes| crate::message_id::payload_id(&bytes))
+}
+
+// The public key inlined in an identity-multihash PeerId (true for all ed25519 peers).
+pub fn peer_public_key(peer: &PeerId) -> Result<identity::PublicKey, String> {
+    let multihash: &libp2p::multihash::Multihash<64> = peer.as_ref();
+    // Code 0x00 is the identity hash, i.e. the protobuf public key itself
+    if multihash.code() != 0 {
+        return Err(format!("Peer {} does not embed its public key", peer));
+    }
+    identity::PublicKey::try_decode_protobuf(multihash.digest())
+        .map_err(|_| format!("Peer {} has an invalid public key", peer))
+}
+
+// Every field that gives the message its meaning, each length-prefixed so values can't
+// run into one another. None for message types never sent to a room.
+fn signed_bytes(room: &str, message: &NetworkMessage) -> Option<Vec<u8>> {
+    signed_bytes_with_id(room, &message.message_id, message)
+}
+
+fn signed_bytes_with_id(room: &str, id: &str, message: &NetworkMessage) -> Option<Vec<u8>> {
+    let mut out = SIGNING_DOMAIN.to_vec();
+    push_field(&mut out, room.as_bytes());
+    push_field(&mut out, id.as_bytes());
+    push_field(&mut out, message.author_id.as_bytes());
+    match message.message_type.as_ref()? {
+        MessageType::Chat(chat) => {
+            push_field(&mut out, b"chat");
+            push_field(&mut out, chat.sender_id.as_bytes());
+            push_field(&mut out, chat.text.as_bytes());
+            push_field(&mut out, &chat.timestamp.to_be_bytes());
+            push_field(&mut out, chat.reply_to.as_bytes());
+        }
+        MessageType::Presence(presence) => {
+            push_field(&mut out, b"presence");
+            push_field(&mut out, presence.sender_id.as_bytes());
+            push_field(
+                &mut out,
+                &(presence.listen_addrs.len() as u32).to_be_bytes(),
+            );
+            for addr in &presence.listen_addrs {
+                push_field(&mut out, addr.as_bytes());
+            }
+            push_field(&mut out, &presence.timestamp.to_be_bytes());
+            // Only when set, so presences from peers predating these fields still verify
+            if !presence.geohash.is_empty() || presence.location_hidden {
+                push_field(&mut out, presence.geohash.as_bytes());
+                push_field(&mut out, &[presence.location_hidden as u8]);
+            }
+        }
+        MessageType::Reaction(reaction) => {
+            push_field(&mut out, b"reaction");
+            push_field(&mut out, reaction.target_id.as_bytes());
+            push_field(&mut out, reaction.emoji.as_bytes());
+            push_field(&mut out, &[reaction.removed as u8]);
+            push_field(&mut out, &reaction.timestamp.to_be_bytes());
+        }
+        MessageType::Edit(edit) => {
+            push_field(&mut out, b"edit");
+            push_field(&mut out, edit.target_id.as_bytes());
+            push_field(&mut out, edit.text.as_bytes());
+            push_field(&mut out, &edit.timestamp.to_be_bytes());
+        }
+        MessageType::Retraction(retraction) => {
+            push_field(&mut out, b"retraction");
+            push_field(&mut out, retraction.target_id.as_bytes());
+            push_field(&mut out, &retraction.timestamp.to_be_bytes());
+        }
+        MessageType::Profile(profile) => {
+            push_field(&mut out, b"profile");
+            push_field(&mut out, profile.nickname.as_bytes());
+            push_field(&mut out, profile.status.as_bytes());
+            push_field(&mut out, &profile.color.to_be_bytes());
+            push_field(&mut out, profile.avatar.as_bytes());
+            push_field(&mut out, &profile.updated_at.to_be_bytes());
+        }
+        MessageType::DirectMessage(_) => return None, // sealed and sent point to point instead
+    }
+    Some(out)
+}
+
+fn push_field(out: &mut Vec<u8>, field: &[u8]) {
+    out.extend_from_slice(&(field.len() as u32).to_be_bytes());
+    out.extend_from_slice(field);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::messages::GlobalChat;
+
+    fn signed_chat(keypair: &identity::Keypair) -> NetworkMessage {
+        let mut message = NetworkMessage {
+            message_type: Some(MessageType::Chat(GlobalChat {
+                sender_id: "alice".to_string(),
+                text: "hello".to_string(),
+                timestamp: 1,
+                reply_to: String::new(),
+            })),
+            ..Default::default()
+        };
+        sign(keypair, "/world", &mut message).unwrap();
+        message
+    }
+
+    #[test]
+    fn verifies_untouched_message() {
+        let keypair = identity::Keypair::generate_ed25519();
+        let message = signed_chat(&keypair);
+        assert_eq!(
+            verify("/world", &message).unwrap(),
+            Some(keypair.public().to_peer_id())
+        );
+    }
+
+    #[test]
+    fn rejects_tampered_field() {
+        let keypair = identity::Keypair::generate_ed25519();
+        let mut message = signed_chat(&keypair);
+        if let Some(MessageType::Chat(chat)) = message.message_type.as_mut() {
+            chat.text = "goodbye".to_string();
+        }
+        assert!(verify("/world", &message).is_err());
+    }
+
+    #[test]
+    fn rejects_other_author() {
+        let keypair = identity::Keypair::generate_ed25519();
+        let mut message = signed_chat(&keypair);
+        message.author_id = identity::Keypair::generate_ed25519()
+            .public()
+            .to_peer_id()
+            .to_string();
+        assert!(verify("/world", &message).is_err());
+    }
+
+    #[test]
+    fn rejects_other_room() {
+        let keypair = identity::Keypair::generate_ed25519();
+        let message = signed_chat(&keypair);
+        assert!(verify("/elsewhere", &message).is_err());
+    }
+
+    #[test]
+    fn id_binds_content_and_author() {
+        let alice = identity::Keypair::generate_ed25519();
+        let message = signed_chat(&alice);
+        assert_eq!(
+            message_id("/world", &message),
+            Some(message.message_id.clone())
+        );
+        assert_eq!(signed_chat(&alice).message_id, message.message_id);
+        assert_ne!(
+            signed_chat(&identity::Keypair::generate_ed25519()).message_id,
+            message.message_id
+        );
+    }
+}
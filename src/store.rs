@@ -0,0 +1,348 @@
+use crate::app::ChatMessage;
+use crate::proto::messages::{StoredMessage, StoredReaction};
+use prost::Message;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// How long and how much message history to keep on disk. `None` means unlimited.
+#[derive(Debug, Clone)]
+pub struct RetentionPolicy {
+    pub max_age_days: Option<u64>,
+    pub max_messages: Option<usize>, // per room or conversation
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            max_age_days: Some(30),
+            max_messages: Some(5000),
+        }
+    }
+}
+
+// Append-only on-disk log of messages, one file per room or DM conversation.
+//
+// Records are length-delimited `StoredMessage` protobufs. Writes only ever append; the
+// retention policy is applied, and superseded records dropped, by rewriting logs once,
+// when the store is opened.
+pub struct MessageStore {
+    dir: PathBuf,
+}
+
+impl MessageStore {
+    // Logs hold decrypted DMs and private room traffic, so like the keystore they are
+    // readable only by us. Permissions are also tightened on stores from older versions.
+    pub fn open(dir: PathBuf, policy: &RetentionPolicy) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        restrict(&dir, 0o700)?;
+        let store = Self { dir };
+        for entry in fs::read_dir(&store.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "log") {
+                restrict(&path, 0o600)?;
+                store.compact(&path, policy)?;
+            }
+        }
+        Ok(store)
+    }
+
+    // Also used to record changes to a message; the newest record for an id wins on load.
+    pub fn append(&self, key: &str, message: &ChatMessage) -> io::Result<()> {
+        let record = to_record(message);
+        let mut file = private_file()
+            .create(true)
+            .append(true)
+            .open(self.log_path(key))?;
+        file.write_all(&record.encode_length_delimited_to_vec())
+    }
+
+    // The newest `limit` messages stamped at or before `before` (or the newest overall), oldest first.
+    pub fn load_before(
+        &self,
+        key: &str,
+        before: Option<u64>,
+        limit: usize,
+    ) -> io::Result<Vec<ChatMessage>> {
+        let mut records = latest(read_log(&self.log_path(key))?);
+        if let Some(before) = before {
+            records.retain(|r| r.timestamp <= before);
+        }
+        let skip = records.len().saturating_sub(limit);
+        Ok(records.into_iter().skip(skip).map(from_record).collect())
+    }
+
+    // Room topics look like "/world" or "/private/<hash>", DM keys like "dm:<peer id>".
+    // Room names can't contain '.', so mapping separators to it never collides.
+    fn log_path(&self, key: &str) -> PathBuf {
+        let name = key.trim_start_matches('/').replace(['/', ':'], ".");
+        self.dir.join(format!("{}.log", name))
+    }
+
+    fn compact(&self, path: &Path, policy: &RetentionPolicy) -> io::Result<()> {
+        let records = read_log(path)?;
+        let original_len = records.len();
+        let records = latest(records);
+
+        let cutoff = policy.max_age_days.map(|days| {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64;
+            now.saturating_sub(days * 24 * 60 * 60 * 1000)
+        });
+        let mut kept: Vec<StoredMessage> = records
+            .into_iter()
+            .filter(|r| cutoff.is_none_or(|cutoff| r.timestamp >= cutoff))
+            .collect();
+        if let Some(max) = policy.max_messages {
+            let skip = kept.len().saturating_sub(max);
+            kept.drain(..skip);
+        }
+
+        if kept.len() == original_len {
+            return Ok(());
+        }
+        if kept.is_empty() {
+            return fs::remove_file(path);
+        }
+
+        // Rewrite via temp file + rename so a crash mid-compaction never loses the log
+        let tmp_path = path.with_extension("log.tmp");
+        let mut buf = Vec::new();
+        for record in &kept {
+            buf.extend_from_slice(&record.encode_length_delimited_to_vec());
+        }
+        private_file()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&tmp_path)?
+            .write_all(&buf)?;
+        fs::rename(&tmp_path, path)
+    }
+}
+
+// Options for creating a file only the owner can read or write.
+fn private_file() -> fs::OpenOptions {
+    let mut options = fs::OpenOptions::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+}
+
+#[cfg(unix)]
+fn restrict(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn restrict(_path: &Path, _mode: u32) -> io::Result<()> {
+    Ok(())
+}
+
+// Reads every record in a log, in file order. A truncated final record (e.g. from a crash
+// mid-append) is ignored rather than failing the whole log.
+fn read_log(path: &Path) -> io::Result<Vec<StoredMessage>> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut records = Vec::new();
+    let mut buf = data.as_slice();
+    while !buf.is_empty() {
+        match StoredMessage::decode_length_delimited(&mut buf) {
+            Ok(record) => records.push(record),
+            Err(_) => break,
+        }
+    }
+    Ok(records)
+}
+
+// Keeps only the newest record for each message id, in timestamp order.
+fn latest(records: Vec<StoredMessage>) -> Vec<StoredMessage> {
+    let mut by_id: HashMap<String, StoredMessage> = HashMap::new();
+    for record in records {
+        by_id.insert(record.id.clone(), record);
+    }
+    let mut records: Vec<StoredMessage> = by_id.into_values().collect();
+    records.sort_by_key(|r| r.timestamp);
+    records
+}
+
+fn to_record(message: &ChatMessage) -> StoredMessage {
+    StoredMessage {
+        id: message.id.clone(),
+        sender: message.sender.clone(),
+        text: message.text.clone(),
+        timestamp: message.timestamp,
+        author_id: message.author.map(|p| p.to_string()).unwrap_or_default(),
+        reply_to: message.reply_to.clone().unwrap_or_default(),
+        edited_at: message.edited_at,
+        retracted: message.retracted,
+        reactions: message
+            .reactions
+            .iter()
+            .map(|(emoji, reactors)| StoredReaction {
+                emoji: emoji.clone(),
+                reactors: reactors.iter().map(|p| p.to_string()).collect(),
+            })
+            .collect(),
+    }
+}
+
+fn from_record(record: StoredMessage) -> ChatMessage {
+    ChatMessage {
+        author: record.author_id.parse().ok(),
+        reply_to: Some(record.reply_to).filter(|id| !id.is_empty()),
+        edited_at: record.edited_at,
+        retracted: record.retracted,
+        reactions: record
+            .reactions
+            .into_iter()
+            .map(|r| {
+                let reactors = r.reactors.iter().filter_map(|p| p.parse().ok()).collect();
+                (r.emoji, reactors)
+            })
+            .collect(),
+        ..ChatMessage::new(record.id, record.sender, record.text, record.timestamp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: &str, text: &str, timestamp: u64) -> ChatMessage {
+        ChatMessage::new(
+            id.to_string(),
+            "alice".to_string(),
+            text.to_string(),
+            timestamp,
+        )
+    }
+
+    fn now_millis() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64
+    }
+
+    fn unlimited() -> RetentionPolicy {
+        RetentionPolicy {
+            max_age_days: None,
+            max_messages: None,
+        }
+    }
+
+    fn texts(messages: &[ChatMessage]) -> Vec<&str> {
+        messages.iter().map(|m| m.text.as_str()).collect()
+    }
+
+    #[test]
+    fn appends_and_newest_record_wins() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = MessageStore::open(dir.path().to_path_buf(), &unlimited()).unwrap();
+        let now = now_millis();
+        store.append("/world", &message("a", "first", now)).unwrap();
+        store
+            .append("/world", &message("b", "second", now + 1))
+            .unwrap();
+        let len = fs::metadata(store.log_path("/world")).unwrap().len();
+        store
+            .append("/world", &message("a", "first, edited", now))
+            .unwrap();
+
+        // The edit is appended, not written over the original
+        assert!(fs::metadata(store.log_path("/world")).unwrap().len() > len);
+        assert_eq!(read_log(&store.log_path("/world")).unwrap().len(), 3);
+        let loaded = store.load_before("/world", None, 10).unwrap();
+        assert_eq!(texts(&loaded), ["first, edited", "second"]);
+        assert_eq!(
+            texts(&store.load_before("/world", Some(now), 10).unwrap()),
+            ["first, edited"]
+        );
+        assert!(store
+            .load_before("dm:someone", None, 10)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn open_compacts_superseded_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = MessageStore::open(dir.path().to_path_buf(), &unlimited()).unwrap();
+        let now = now_millis();
+        for text in ["one", "two", "three"] {
+            store.append("/world", &message("a", text, now)).unwrap();
+        }
+
+        let store = MessageStore::open(dir.path().to_path_buf(), &unlimited()).unwrap();
+        let records = read_log(&store.log_path("/world")).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].text, "three");
+    }
+
+    #[test]
+    fn open_prunes_by_age_and_count() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = MessageStore::open(dir.path().to_path_buf(), &unlimited()).unwrap();
+        let now = now_millis();
+        let day = 24 * 60 * 60 * 1000;
+        store
+            .append("/world", &message("old", "old", now - 10 * day))
+            .unwrap();
+        for (i, id) in ["a", "b", "c"].iter().enumerate() {
+            store
+                .append("/world", &message(id, id, now + i as u64))
+                .unwrap();
+        }
+        store
+            .append("/dev", &message("gone", "gone", now - 10 * day))
+            .unwrap();
+
+        let policy = RetentionPolicy {
+            max_age_days: Some(7),
+            max_messages: Some(2),
+        };
+        let store = MessageStore::open(dir.path().to_path_buf(), &policy).unwrap();
+        assert_eq!(
+            texts(&store.load_before("/world", None, 10).unwrap()),
+            ["b", "c"]
+        );
+        // A log with nothing left is removed entirely
+        assert!(!store.log_path("/dev").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_logs_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        let dir = tempfile::tempdir().unwrap();
+        let messages = dir.path().join("messages");
+        let store = MessageStore::open(messages.clone(), &unlimited()).unwrap();
+        store
+            .append("/world", &message("a", "hi", now_millis()))
+            .unwrap();
+        assert_eq!(mode(&messages), 0o700);
+        assert_eq!(mode(&store.log_path("/world")), 0o600);
+
+        // Stores left readable by older versions are tightened on open
+        fs::set_permissions(&messages, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::set_permissions(store.log_path("/world"), fs::Permissions::from_mode(0o644)).unwrap();
+        let store = MessageStore::open(messages.clone(), &unlimited()).unwrap();
+        assert_eq!(mode(&messages), 0o700);
+        assert_eq!(mode(&store.log_path("/world")), 0o600);
+    }
+}
@@ -7,15 +7,33 @@ use ratatui::{
     Frame,
 };
 
-use crate::app::App;
+use crate::app::{App, ChatMessage};
+
+// The HUD palette; overridable per role from the `[theme]` config section.
+#[derive(Debug, Clone, Copy)]
+pub struct Theme {
+    pub primary: Color,
+    pub accent: Color,
+    pub highlight: Color,
+    pub secondary: Color,
+    pub dim: Color,
+    pub text: Color,
+    pub background: Color,
+}
 
-const NEON_CYAN: Color = Color::Rgb(0, 255, 255);
-const NEON_PINK: Color = Color::Rgb(255, 45, 149);
-const NEON_YELLOW: Color = Color::Rgb(255, 215, 0);
-const NEON_VIOLET: Color = Color::Rgb(138, 43, 226);
-const HUD_DIM: Color = Color::Rgb(80, 80, 100);
-const HUD_TEXT: Color = Color::Rgb(180, 200, 220);
-const HUD_BG: Color = Color::Rgb(8, 8, 18);
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            primary: Color::Rgb(0, 255, 255),
+            accent: Color::Rgb(255, 45, 149),
+            highlight: Color::Rgb(255, 215, 0),
+            secondary: Color::Rgb(138, 43, 226),
+            dim: Color::Rgb(80, 80, 100),
+            text: Color::Rgb(180, 200, 220),
+            background: Color::Rgb(8, 8, 18),
+        }
+    }
+}
 
 pub struct GlobeWidget<'a> {
     pub app: &'a mut App,
@@ -24,105 +42,204 @@ pub struct GlobeWidget<'a> {
 
 impl<'a> Widget for GlobeWidget<'a> {
     fn render(self, area: Rect, buf: &mut Buffer) {
+        let theme = self.app.theme;
         let inner = area.inner(ratatui::layout::Margin {
             vertical: 1,
             horizontal: 1,
         });
+        self.app.globe_area = inner;
         if inner.width == 0 || inner.height == 0 {
             return;
         }
 
-        // If terminal resized, precompute all spatial projection math
-        if self.app.last_width != inner.width || self.app.last_height != inner.height {
+        // If terminal resized, the view changed, or the camera tilted or zoomed, precompute
+        // all spatial projection math. Spinning only shifts longitudes, so it doesn't
+        // invalidate the cache.
+        let detail = self.app.globe_render.detail(inner.width);
+        let camera = self.app.camera;
+        let flat = self.app.projection.flat();
+        let cx = inner.width as f64 / 2.0;
+        let cy = inner.height as f64 / 2.0;
+        let r = ((inner.height as f64 / 2.0) - 1.0) * camera.zoom();
+        // Half the flat map's size in cells, as large as fits. Cells are about 0.45 as wide
+        // as they are tall.
+        let (half_width, half_height) = match flat {
+            Some(map) => {
+                let half_height = cy.min(cx * 0.45 / map.aspect());
+                (half_height * map.aspect() / 0.45, half_height)
+            }
+            None => (0.0, 0.0),
+        };
+        if self.app.last_width != inner.width
+            || self.app.last_height != inner.height
+            || self.app.projection_detail != detail
+            || self.app.projection_view != self.app.projection
+            || self.app.projection_tilt != camera.tilt
+            || self.app.projection_zoom != camera.zoom()
+        {
             self.app.last_width = inner.width;
             self.app.last_height = inner.height;
+            self.app.projection_detail = detail;
+            self.app.projection_view = self.app.projection;
+            self.app.projection_tilt = camera.tilt;
+            self.app.projection_zoom = camera.zoom();
             self.app.projection_cache.clear();
 
-            let width = inner.width as f64;
-            let height = inner.height as f64;
-            let cx = width / 2.0;
-            let cy = height / 2.0;
-            let r = (height / 2.0) - 1.0;
+            let (cols, rows) = detail.sub_cells();
+
+            // Direction from the globe's centre to the surface under a sample, in the same
+            // spun frame as `sun_vector`
+            let surface_normal = |px: f64, py: f64| match flat {
+                // The map scrolls with the spin, so longitude here is relative to the camera
+                Some(map) => {
+                    let (lat, lon) =
+                        map.unproject((px - cx) / half_width, (cy - py) / half_height)?;
+                    Some((lat.cos() * lon.sin(), lat.sin(), lat.cos() * lon.cos()))
+                }
+                None => {
+                    let dy = (py - cy) / r;
+                    let dx = (px - cx) / r * 0.45;
+                    let d2 = dx * dx + dy * dy;
+                    // Y points down on screen, up on the globe. With the tilt undone, only
+                    // the spin separates this from the globe's own axes.
+                    (d2 <= 1.0).then(|| camera.untilt((dx, -dy, f64::sqrt(1.0 - d2))))
+                }
+            };
 
             if r > 0.0 {
                 for y in 0..inner.height {
-                    let dy = (y as f64 - cy) / r;
-                    let dy_sq = dy * dy;
-
-                    // Reusable unmodified Y for latitude math (points down)
-                    let y0 = dy;
-                    let normal_y = -dy;
-
                     for x in 0..inner.width {
-                        let dx = (x as f64 - cx) / r * 0.45;
-                        let d2 = dx * dx + dy_sq;
-
-                        if d2 <= 1.0 {
-                            let dz = f64::sqrt(1.0 - d2);
-                            let normal_x = dx;
-                            let normal_z = dz;
-
-                            // Intensity is completely static for a given pixel if sun doesnt move
-                            let intensity = normal_x * self.sun_vector.0
-                                + normal_y * self.sun_vector.1
-                                + normal_z * self.sun_vector.2;
-
-                            // Precalculate the Unrotated mapping coordinates
-                            let original_lon = dz.atan2(dx);
-                            let original_u = (original_lon + std::f64::consts::PI)
-                                / (2.0 * std::f64::consts::PI);
-
-                            let lat = (-y0).asin();
-                            let v = 1.0 - (lat + std::f64::consts::PI / 2.0) / std::f64::consts::PI;
-                            let map_y = ((v * crate::globe::EARTH_MAP_HEIGHT as f64) as usize)
-                                .clamp(0, crate::globe::EARTH_MAP_HEIGHT - 1);
-
-                            self.app.projection_cache.push(crate::app::CachedPoint {
-                                screen_x: inner.x + x,
-                                screen_y: inner.y + y,
-                                original_u,
-                                map_y,
-                                intensity,
-                            });
+                        // Sub-samples are spread evenly around where the single sample sits
+                        for sx in 0..cols {
+                            for sy in 0..rows {
+                                let px = x as f64 + (sx as f64 + 0.5) / cols as f64 - 0.5;
+                                let py = y as f64 + (sy as f64 + 0.5) / rows as f64 - 0.5;
+
+                                if let Some(normal) = surface_normal(px, py) {
+                                    // Longitude under this sample before rotation, 0 at the
+                                    // centre and increasing to the right, the same as the
+                                    // peer markers
+                                    let original_lon = normal.0.atan2(normal.2);
+                                    let original_u = (original_lon + std::f64::consts::PI)
+                                        / (2.0 * std::f64::consts::PI);
+
+                                    self.app.projection_cache.push(crate::app::CachedPoint {
+                                        screen_x: inner.x + x,
+                                        screen_y: inner.y + y,
+                                        sub: (sx * rows + sy) as u8,
+                                        original_u,
+                                        map_y: detail.map_row(normal.1.clamp(-1.0, 1.0).asin()),
+                                        normal,
+                                    });
+                                }
+                            }
                         }
                     }
                 }
             }
         }
 
-        let rot_u = self.app.rotation_y / (2.0 * std::f64::consts::PI);
-        let map_width = crate::globe::EARTH_MAP_WIDTH as f64;
+        let rot_u = camera.yaw / (2.0 * std::f64::consts::PI);
 
         // Scanline phase shifts every few ticks for subtle CRT movement
         let scanline_offset = (self.app.tick_count / 3) as u16;
 
-        for p in &self.app.projection_cache {
-            if p.screen_x < inner.right() && p.screen_y < inner.bottom() {
+        // Samples of one cell are adjacent in the cache
+        for cell in self
+            .app
+            .projection_cache
+            .chunk_by(|a, b| (a.screen_x, a.screen_y) == (b.screen_x, b.screen_y))
+        {
+            let (screen_x, screen_y) = (cell[0].screen_x, cell[0].screen_y);
+            if screen_x >= inner.right() || screen_y >= inner.bottom() {
+                continue;
+            }
+            let samples = cell.iter().map(|p| {
                 //  mapped rotating longitude
-                let u = (p.original_u - rot_u).rem_euclid(1.0);
-                let map_x = ((u * map_width) as usize).clamp(0, crate::globe::EARTH_MAP_WIDTH - 1);
-
-                let is_land = crate::globe::EARTH_MAP[p.map_y].as_bytes()[map_x] == b'#';
-
-                let (character, mut color) = crate::globe::get_appearance(is_land, p.intensity);
-
-                // Scanline dimming every 3rd row gets slightly darker
-                if (p.screen_y + scanline_offset) % 3 == 0 {
-                    color = dim_color(color, 0.75);
+                let u = (p.original_u + rot_u).rem_euclid(1.0);
+                let is_land = detail.is_land(p.map_y, u);
+                let intensity = p.normal.0 * self.sun_vector.0
+                    + p.normal.1 * self.sun_vector.1
+                    + p.normal.2 * self.sun_vector.2;
+                (p.sub, is_land, intensity)
+            });
+
+            // Scanline dimming every 3rd row gets slightly darker
+            let scanline = (screen_y + scanline_offset).is_multiple_of(3);
+            let shade = |color| {
+                if scanline {
+                    dim_color(color, 0.75)
+                } else {
+                    color
+                }
+            };
+            let Some(target) = buf.cell_mut((screen_x, screen_y)) else {
+                continue;
+            };
+
+            match detail {
+                crate::globe::Detail::Ascii => {
+                    for (_, is_land, intensity) in samples {
+                        let (character, color) =
+                            crate::globe::get_appearance(is_land, intensity, self.app.twilight);
+                        target.set_char(character).set_fg(shade(color));
+                    }
+                }
+                // Upper half in the foreground colour, lower half in the background
+                crate::globe::Detail::HalfBlock => {
+                    let mut halves = [None, None];
+                    for (sub, is_land, intensity) in samples {
+                        let (_, color) =
+                            crate::globe::get_appearance(is_land, intensity, self.app.twilight);
+                        halves[sub as usize] = Some(shade(color));
+                    }
+                    match halves {
+                        [Some(top), Some(bottom)] => {
+                            target.set_char('▀').set_fg(top).set_bg(bottom);
+                        }
+                        [Some(top), None] => {
+                            target.set_char('▀').set_fg(top);
+                        }
+                        [None, Some(bottom)] => {
+                            target.set_char('▄').set_fg(bottom);
+                        }
+                        [None, None] => {}
+                    }
+                }
+                // Land samples become raised dots; cells with no land draw ocean as before
+                crate::globe::Detail::Braille => {
+                    let (mut dots, mut land, mut light, mut count) = (0u8, 0, 0.0, 0);
+                    let mut ocean_light = 0.0;
+                    for (sub, is_land, intensity) in samples {
+                        if is_land {
+                            dots |= 1 << sub;
+                            land += 1;
+                            light += intensity;
+                        } else {
+                            ocean_light += intensity;
+                        }
+                        count += 1;
+                    }
+                    let (character, color) = if land > 0 {
+                        let (_, color) = crate::globe::get_appearance(
+                            true,
+                            light / land as f64,
+                            self.app.twilight,
+                        );
+                        (crate::globe::braille(dots), color)
+                    } else {
+                        crate::globe::get_appearance(
+                            false,
+                            ocean_light / count as f64,
+                            self.app.twilight,
+                        )
+                    };
+                    target.set_char(character).set_fg(shade(color));
                 }
-
-                buf.cell_mut((p.screen_x, p.screen_y))
-                    .unwrap()
-                    .set_char(character)
-                    .set_fg(color);
             }
         }
 
         // Render Peer Markers
-        let r = (inner.height as f64 / 2.0) - 1.0;
-        let cx = inner.width as f64 / 2.0;
-        let cy = inner.height as f64 / 2.0;
-
         // Breathing pulse — alternate marker glyph on tick
         let marker_char = if self.app.tick_count % 6 < 3 {
             '◈'
@@ -130,26 +247,81 @@ impl<'a> Widget for GlobeWidget<'a> {
             '◇'
         };
 
-        for (_, (lat, lon, _)) in &self.app.peer_locations {
-            let lat_rad = lat.to_radians();
-            let lon_rad = lon.to_radians();
-
-            // The texture is mapped such that longitude 0 is the center.
-            //  revolve the sphere by subtracting rotation_y.
-            let current_lon = lon_rad - self.app.rotation_y;
+        // Cell a (lat, lon) in degrees lands on. Flat maps show everyone; the globe only the
+        // hemisphere facing the camera, and zoomed in, points can fall outside the panel.
+        let to_screen = |lat: f64, lon: f64| {
+            let (x, y) = match flat {
+                Some(map) => {
+                    let lon = (lon.to_radians() - camera.yaw + std::f64::consts::PI)
+                        .rem_euclid(std::f64::consts::TAU)
+                        - std::f64::consts::PI;
+                    let (x, y) = map.project(lat.to_radians(), lon);
+                    (x * half_width, y * half_height)
+                }
+                None => {
+                    let (x, y, z) = camera.project(lat, lon);
+                    if z <= 0.0 {
+                        return None;
+                    }
+                    (x * r / 0.45, y * r)
+                }
+            };
+            // Screen Y points downwards
+            let (screen_x, screen_y) = ((cx + x).round(), (cy - y).round());
+            ((0.0..inner.width as f64).contains(&screen_x)
+                && (0.0..inner.height as f64).contains(&screen_y))
+            .then(|| (inner.x + screen_x as u16, inner.y + screen_y as u16))
+        };
 
-            let x = lat_rad.cos() * current_lon.sin();
-            let y = -lat_rad.sin(); // Maps to screen Y downwards
-            let z = lat_rad.cos() * current_lon.cos();
+        // Arcs from us to each located peer, solid when direct and dashed when relayed, with
+        // incoming messages travelling along them. Drawn first so markers stay on top.
+        if let Some(own) = self.app.own_location {
+            for (peer, (lat, lon, _)) in &self.app.peer_locations {
+                let relayed = self.app.relayed_peers.contains(peer);
+                let (_, color) = signal_bar(&theme, relayed);
+                let arc = crate::globe::great_circle(own, (*lat, *lon));
+                for (i, &(lat, lon)) in arc.iter().enumerate() {
+                    if relayed && i % 3 == 2 {
+                        continue;
+                    }
+                    if let Some(cell) = to_screen(lat, lon).and_then(|at| buf.cell_mut(at)) {
+                        cell.set_char('·').set_fg(dim_color(color, 0.7));
+                    }
+                }
 
-            // If the point is on the hemisphere facing the camera
-            if z > 0.0 {
-                let screen_x = (cx + (x * r / 0.45)).round() as u16;
-                let screen_y = (cy + y * r).round() as u16;
+                // Pulses start at the sender's end, with a short fading tail behind them
+                for pulse in self.app.pulses.iter().filter(|pulse| pulse.from == *peer) {
+                    let age = self.app.tick_count.wrapping_sub(pulse.started);
+                    let progress = age as f64 / crate::app::PULSE_TICKS as f64;
+                    let head = ((1.0 - progress) * (arc.len() - 1) as f64).round() as usize;
+                    for (behind, glyph) in ['●', '•', '·'].into_iter().enumerate() {
+                        let Some(&(lat, lon)) = arc.get(head + behind) else {
+                            break;
+                        };
+                        if let Some(cell) = to_screen(lat, lon).and_then(|at| buf.cell_mut(at)) {
+                            let fade = 1.0 - behind as f64 * 0.3;
+                            cell.set_char(glyph)
+                                .set_fg(dim_color(theme.highlight, fade));
+                        }
+                    }
+                }
+            }
+        }
 
-                if screen_x < inner.width && screen_y < inner.height {
-                    if let Some(cell) = buf.cell_mut((inner.x + screen_x, inner.y + screen_y)) {
-                        cell.set_char(marker_char).set_fg(NEON_YELLOW);
+        for (peer, (lat, lon, _)) in &self.app.peer_locations {
+            if let Some(cell) = to_screen(*lat, *lon).and_then(|at| buf.cell_mut(at)) {
+                // Peers with a profile show their own avatar, breathing in their colour
+                match self.app.profile_of(peer) {
+                    Some(profile) => {
+                        let mut color = profile_color(profile);
+                        if marker_char == '◇' {
+                            color = dim_color(color, 0.6);
+                        }
+                        let avatar = profile.avatar.chars().next().unwrap_or(marker_char);
+                        cell.set_char(avatar).set_fg(color);
+                    }
+                    None => {
+                        cell.set_char(marker_char).set_fg(theme.highlight);
                     }
                 }
             }
@@ -170,13 +342,13 @@ fn dim_color(color: Color, factor: f64) -> Color {
 }
 
 // Returns a connection status indicator dot and color based on peer count.
-fn connection_indicator(peer_count: usize, tick: u64) -> (char, Color) {
+fn connection_indicator(theme: &Theme, peer_count: usize, tick: u64) -> (char, Color) {
     if peer_count == 0 {
         // Blink red when disconnected
         let ch = if tick % 6 < 3 { '●' } else { '○' };
         (ch, Color::Rgb(255, 50, 50))
     } else if peer_count < 3 {
-        ('●', NEON_YELLOW)
+        ('●', theme.highlight)
     } else {
         ('●', Color::Rgb(0, 255, 100))
     }
@@ -195,15 +367,16 @@ fn network_pulse(tick: u64) -> &'static str {
 }
 
 // Format a signal strength bar for a peer.
-fn signal_bar(is_relayed: bool) -> (&'static str, Color) {
+fn signal_bar(theme: &Theme, is_relayed: bool) -> (&'static str, Color) {
     if is_relayed {
-        ("▰▰▱▱▱", NEON_YELLOW)
+        ("▰▰▱▱▱", theme.highlight)
     } else {
         ("▰▰▰▰▱", Color::Rgb(0, 255, 100))
     }
 }
 
 pub fn render(f: &mut Frame, app: &mut App) {
+    let theme = app.theme;
     let area = f.area();
 
     if !app.boot_complete {
@@ -211,42 +384,52 @@ pub fn render(f: &mut Frame, app: &mut App) {
         return;
     }
 
-    let (conn_dot, conn_color) = connection_indicator(app.peers.len(), app.tick_count);
+    let (conn_dot, conn_color) = connection_indicator(&theme, app.peers.len(), app.tick_count);
     let pulse = network_pulse(app.tick_count);
 
     let title = Line::from(vec![
-        Span::styled("╡ ", Style::default().fg(HUD_DIM)),
+        Span::styled("╡ ", Style::default().fg(theme.dim)),
         Span::styled(
             "TERRA-LINK",
-            Style::default().fg(NEON_PINK).add_modifier(Modifier::BOLD),
+            Style::default()
+                .fg(theme.accent)
+                .add_modifier(Modifier::BOLD),
         ),
-        Span::styled(" v0.1.0 ", Style::default().fg(HUD_DIM)),
-        Span::styled("╞", Style::default().fg(HUD_DIM)),
+        Span::styled(" v0.1.0 ", Style::default().fg(theme.dim)),
+        Span::styled("╞", Style::default().fg(theme.dim)),
     ]);
 
     let status_bar = Line::from(vec![
-        Span::styled("╡ ", Style::default().fg(HUD_DIM)),
+        Span::styled("╡ ", Style::default().fg(theme.dim)),
         Span::styled(format!("{conn_dot}"), Style::default().fg(conn_color)),
         Span::styled(
             format!(" NODES: {} ", app.peers.len()),
-            Style::default().fg(HUD_TEXT),
+            Style::default().fg(theme.text),
         ),
-        Span::styled("│ ", Style::default().fg(HUD_DIM)),
-        Span::styled(format!("{pulse}"), Style::default().fg(NEON_CYAN)),
-        Span::styled(" MESH ", Style::default().fg(HUD_TEXT)),
-        Span::styled("╞", Style::default().fg(HUD_DIM)),
+        Span::styled("│ ", Style::default().fg(theme.dim)),
+        Span::styled(pulse.to_string(), Style::default().fg(theme.primary)),
+        Span::styled(" MESH ", Style::default().fg(theme.text)),
+        Span::styled("╞", Style::default().fg(theme.dim)),
     ]);
 
     let block = Block::default()
         .title_top(title)
         .title_bottom(status_bar)
         .borders(Borders::ALL)
-        .border_style(Style::default().fg(NEON_CYAN))
-        .style(Style::default().bg(HUD_BG));
+        .border_style(Style::default().fg(theme.primary))
+        .style(Style::default().bg(theme.background));
 
     f.render_widget(block, area);
 
-    let sun_vector = (1.0, 0.2, 0.0);
+    // The sun as seen from the globe's centre, in the same spun (but untilted) frame as the
+    // cached surface normals
+    let (sun_lat, sun_lon) = crate::globe::subsolar_point(chrono::Utc::now());
+    let (sun_lat, sun_lon) = (sun_lat.to_radians(), sun_lon.to_radians() - app.camera.yaw);
+    let sun_vector = (
+        sun_lat.cos() * sun_lon.sin(),
+        sun_lat.sin(),
+        sun_lat.cos() * sun_lon.cos(),
+    );
     let globe = GlobeWidget {
         app: &mut *app,
         sun_vector,
@@ -261,6 +444,7 @@ pub fn render(f: &mut Frame, app: &mut App) {
 }
 
 fn render_network_info(f: &mut Frame, app: &App) {
+    let theme = app.theme;
     let area = f.area();
     let mut lines = vec![];
 
@@ -271,18 +455,26 @@ fn render_network_info(f: &mut Frame, app: &App) {
         } else {
             full
         };
+        let color = profile_color(&app.profile);
         lines.push(Line::from(vec![
-            Span::styled("⌘ ", Style::default().fg(NEON_PINK)),
-            Span::styled(short, Style::default().fg(HUD_TEXT)),
+            Span::styled(
+                format!("{} ", app.profile.avatar),
+                Style::default().fg(color),
+            ),
+            Span::styled(short, Style::default().fg(theme.text)),
+            Span::styled(
+                format!("  {}", app.profile.nickname),
+                Style::default().fg(color),
+            ),
         ]));
     }
 
     lines.push(Line::from(vec![Span::styled(
         format!("  Peers: {}", app.peers.len()),
-        Style::default().fg(HUD_DIM),
+        Style::default().fg(theme.dim),
     )]));
 
-    for peer in &app.peers {
+    for (idx, peer) in app.peers.iter().enumerate() {
         let full_id = peer.to_string();
         let short_id = if full_id.len() > 8 {
             &full_id[full_id.len() - 8..]
@@ -290,20 +482,62 @@ fn render_network_info(f: &mut Frame, app: &App) {
             &full_id
         };
 
-        let (bar, bar_color) = signal_bar(false);
+        let (bar, bar_color) = signal_bar(&theme, app.relayed_peers.contains(peer));
 
-        if let Some((_, _, loc)) = app.peer_locations.get(peer) {
-            lines.push(Line::from(vec![
-                Span::styled("  ⌘ ", Style::default().fg(NEON_YELLOW)),
-                Span::styled(format!("{loc}"), Style::default().fg(HUD_TEXT)),
-                Span::styled(format!("  {bar}"), Style::default().fg(bar_color)),
-            ]));
+        // Cursor for picking a DM target, envelope for unread direct messages
+        let cursor = if idx == app.selected_peer { "▸" } else { " " };
+        let unread = if app.unread_dms.contains(peer) {
+            " ✉"
         } else {
-            lines.push(Line::from(vec![
-                Span::styled("  ◇ ", Style::default().fg(HUD_DIM)),
-                Span::styled(short_id.to_string(), Style::default().fg(HUD_DIM)),
-            ]));
+            ""
+        };
+
+        // Profile avatar and nickname where the peer has published them
+        let profile = app.profile_of(peer);
+        let mut spans = vec![Span::styled(cursor, Style::default().fg(theme.accent))];
+        let location = app.peer_locations.get(peer);
+        match profile {
+            Some(profile) => spans.push(Span::styled(
+                format!(" {} ", profile.avatar),
+                Style::default().fg(profile_color(profile)),
+            )),
+            None if location.is_some() => {
+                spans.push(Span::styled(" ⌘ ", Style::default().fg(theme.highlight)))
+            }
+            None => spans.push(Span::styled(" ◇ ", Style::default().fg(theme.dim))),
+        }
+        if let Some(profile) = profile.filter(|p| !p.nickname.is_empty()) {
+            spans.push(Span::styled(
+                format!("{} ", profile.nickname),
+                Style::default().fg(profile_color(profile)),
+            ));
+        }
+        match location {
+            Some((_, _, loc)) => {
+                spans.push(Span::styled(
+                    loc.to_string(),
+                    Style::default().fg(theme.text),
+                ));
+                spans.push(Span::styled(
+                    format!("  {bar}"),
+                    Style::default().fg(bar_color),
+                ));
+            }
+            None => spans.push(Span::styled(
+                short_id.to_string(),
+                Style::default().fg(theme.dim),
+            )),
         }
+        spans.push(Span::styled(unread, Style::default().fg(theme.accent)));
+        if let Some(profile) = profile.filter(|p| !p.status.is_empty()) {
+            spans.push(Span::styled(
+                format!("  {}", profile.status),
+                Style::default()
+                    .fg(theme.dim)
+                    .add_modifier(Modifier::ITALIC),
+            ));
+        }
+        lines.push(Line::from(spans));
     }
 
     let info_height = (3 + app.peers.len() as u16).max(5);
@@ -319,52 +553,178 @@ fn render_network_info(f: &mut Frame, app: &App) {
             Block::default()
                 .borders(Borders::ALL)
                 .title(Line::from(vec![
-                    Span::styled("┤ ", Style::default().fg(HUD_DIM)),
-                    Span::styled("NETWORK", Style::default().fg(NEON_CYAN)),
-                    Span::styled(" ├", Style::default().fg(HUD_DIM)),
+                    Span::styled("┤ ", Style::default().fg(theme.dim)),
+                    Span::styled("NETWORK", Style::default().fg(theme.primary)),
+                    Span::styled(" ├", Style::default().fg(theme.dim)),
                 ]))
-                .border_style(Style::default().fg(NEON_CYAN.into())),
+                .border_style(Style::default().fg(theme.primary)),
         )
-        .style(Style::default().fg(HUD_TEXT).bg(HUD_BG));
+        .style(Style::default().fg(theme.text).bg(theme.background));
 
     f.render_widget(Clear, info_area);
     f.render_widget(info_widget, info_area);
 }
 
 fn render_chat(f: &mut Frame, app: &mut App) {
+    let theme = app.theme;
     let area = f.area();
 
+    // An open DM conversation takes over the feed pane
+    let (room, title) = match app.dm_peer {
+        Some(peer) => {
+            let full = peer.to_string();
+            let short = full[full.len().saturating_sub(8)..].to_string();
+            (
+                app.direct_messages.get(&peer),
+                vec![
+                    Span::styled("┤ ", Style::default().fg(theme.dim)),
+                    Span::styled("DIRECT", Style::default().fg(theme.accent)),
+                    Span::styled(" :: ", Style::default().fg(theme.dim)),
+                    Span::styled(short, Style::default().fg(theme.secondary)),
+                    Span::styled(" ├", Style::default().fg(theme.dim)),
+                ],
+            )
+        }
+        None => {
+            let room = &app.rooms[app.active_room];
+            let label = if room.name == crate::network::DEFAULT_ROOM {
+                "GLOBAL FEED"
+            } else if room.private {
+                "PRIVATE"
+            } else {
+                "ROOM"
+            };
+            let mut title = vec![
+                Span::styled("┤ ", Style::default().fg(theme.dim)),
+                Span::styled(label, Style::default().fg(theme.primary)),
+                Span::styled(" ::", Style::default().fg(theme.dim)),
+            ];
+            // Room switcher: every joined room, active one highlighted, unread counts on the rest
+            for (idx, r) in app.rooms.iter().enumerate() {
+                let style = if idx == app.active_room {
+                    Style::default()
+                        .fg(theme.secondary)
+                        .add_modifier(Modifier::BOLD)
+                } else {
+                    Style::default().fg(theme.dim)
+                };
+                let lock = if r.private { "🔒" } else { "" };
+                title.push(Span::styled(format!(" {}{}", r.label, lock), style));
+                if r.unread > 0 {
+                    title.push(Span::styled(
+                        format!("({})", r.unread),
+                        Style::default().fg(theme.accent),
+                    ));
+                }
+            }
+            title.push(Span::styled(" ├", Style::default().fg(theme.dim)));
+            (Some(room), title)
+        }
+    };
+
+    // Scrolled back rooms show the window ending `scroll` messages above the newest
+    let (messages, scroll, new_below, first_unread) = room
+        .map(|r| {
+            (
+                r.messages.as_slice(),
+                r.scroll,
+                r.new_below,
+                r.first_unread.as_deref(),
+            )
+        })
+        .unwrap_or_default();
+    let end = messages.len().saturating_sub(scroll);
+    let rows = if scroll > 0 { 9 } else { 10 }; // leave a row for the "below" indicator
+
     let mut chat_lines = vec![];
-    for (sender, text) in app.chat_messages.iter().rev().take(10).rev() {
-        let short_id = if sender.len() > 8 {
-            &sender[sender.len() - 8..]
+    for message in messages[..end].iter().rev().take(rows).rev() {
+        if first_unread == Some(message.id.as_str()) {
+            chat_lines.push(Line::from(Span::styled(
+                "──────── unread ────────",
+                Style::default().fg(theme.accent),
+            )));
+        }
+        // Replies quote the start of the message they answer
+        if let Some(reply_to) = &message.reply_to {
+            let quoted = match messages.iter().find(|m| &m.id == reply_to) {
+                Some(original) if original.retracted => "(message deleted)".to_string(),
+                Some(original) => {
+                    let excerpt: String = original.text.chars().take(24).collect();
+                    let name = original.author.map(|p| p.to_string());
+                    let name = name.as_deref().unwrap_or(&original.sender);
+                    format!("{}: {}", short_name(name), excerpt)
+                }
+                None => "(earlier message)".to_string(),
+            };
+            chat_lines.push(Line::from(Span::styled(
+                format!("  ↪ {}", quoted),
+                Style::default().fg(theme.dim),
+            )));
+        }
+
+        let selected = app.selected_message.as_deref() == Some(message.id.as_str());
+        let mut sender_style = Style::default().fg(theme.accent);
+        if selected {
+            sender_style = sender_style.add_modifier(Modifier::REVERSED);
+        }
+        let mut spans = vec![
+            Span::styled(
+                format_timestamp(message.timestamp, &app.time_format),
+                Style::default().fg(theme.dim),
+            ),
+            Span::raw(" "),
+        ];
+        spans.extend(sender_spans(app, message, sender_style));
+        spans.push(Span::raw(" "));
+        if message.retracted {
+            spans.push(Span::styled(
+                "(message deleted)",
+                Style::default()
+                    .fg(theme.dim)
+                    .add_modifier(Modifier::ITALIC),
+            ));
+        } else {
+            spans.push(Span::styled(&message.text, Style::default().fg(theme.text)));
+            if message.edited_at > 0 {
+                spans.push(Span::styled(" (edited)", Style::default().fg(theme.dim)));
+            }
+            for (emoji, reactors) in &message.reactions {
+                spans.push(Span::styled(
+                    format!(" {}{}", emoji, reactors.len()),
+                    Style::default().fg(theme.highlight),
+                ));
+            }
+        }
+        chat_lines.push(Line::from(spans));
+    }
+    // Separators and reply quotes take rows too; keep the newest lines
+    if chat_lines.len() > rows {
+        chat_lines.drain(..chat_lines.len() - rows);
+    }
+    if scroll > 0 {
+        let below = if new_below > 0 {
+            format!("▼ {} new below", new_below)
         } else {
-            sender
+            format!("▼ {} more below", scroll)
         };
-        chat_lines.push(Line::from(vec![
-            Span::styled(format!("{short_id}: "), Style::default().fg(NEON_PINK)),
-            Span::styled(text, Style::default().fg(HUD_TEXT)),
-        ]));
+        chat_lines.push(Line::from(Span::styled(
+            below,
+            Style::default().fg(theme.highlight),
+        )));
     }
 
     let chat_widget = Paragraph::new(chat_lines)
         .block(
             Block::default()
                 .borders(Borders::ALL)
-                .title(Line::from(vec![
-                    Span::styled("┤ ", Style::default().fg(HUD_DIM)),
-                    Span::styled("GLOBAL FEED", Style::default().fg(NEON_CYAN)),
-                    Span::styled(" :: ", Style::default().fg(HUD_DIM)),
-                    Span::styled("/world", Style::default().fg(NEON_VIOLET)),
-                    Span::styled(" ├", Style::default().fg(HUD_DIM)),
-                ]))
-                .border_style(Style::default().fg(NEON_CYAN)),
+                .title(Line::from(title))
+                .border_style(Style::default().fg(theme.primary)),
         )
-        .style(Style::default().fg(HUD_TEXT).bg(HUD_BG))
+        .style(Style::default().fg(theme.text).bg(theme.background))
         .wrap(Wrap { trim: true });
 
     let chat_area = Rect {
-        x: area.right().saturating_sub(42).max(0),
+        x: area.right().saturating_sub(42),
         y: area.bottom().saturating_sub(14), // 12 + 2 for keybind footer
         width: 42.min(area.width),
         height: 12,
@@ -374,19 +734,26 @@ fn render_chat(f: &mut Frame, app: &mut App) {
     f.render_widget(chat_widget, chat_area);
 
     if app.input_mode {
-        let input_text = format!(">_ {}", app.input_buffer);
+        let prefix = if app.editing.is_some() {
+            "✎ "
+        } else if app.reply_to.is_some() {
+            "↪ "
+        } else {
+            ">_ "
+        };
+        let input_text = format!("{}{}", prefix, app.input_buffer);
         let input_widget = Paragraph::new(input_text.as_str())
             .block(
                 Block::default()
                     .borders(Borders::ALL)
                     .title(Line::from(vec![
-                        Span::styled("┤ ", Style::default().fg(HUD_DIM)),
-                        Span::styled("TRANSMIT", Style::default().fg(NEON_YELLOW)),
-                        Span::styled(" ├", Style::default().fg(HUD_DIM)),
+                        Span::styled("┤ ", Style::default().fg(theme.dim)),
+                        Span::styled("TRANSMIT", Style::default().fg(theme.highlight)),
+                        Span::styled(" ├", Style::default().fg(theme.dim)),
                     ]))
-                    .border_style(Style::default().fg(NEON_YELLOW)),
+                    .border_style(Style::default().fg(theme.highlight)),
             )
-            .style(Style::default().fg(Color::White).bg(HUD_BG));
+            .style(Style::default().fg(Color::White).bg(theme.background));
 
         let input_area = Rect {
             x: chat_area.x,
@@ -398,31 +765,159 @@ fn render_chat(f: &mut Frame, app: &mut App) {
         f.render_widget(Clear, input_area);
         f.render_widget(input_widget, input_area);
 
-        // Cursor after ">_ " prefix (3 chars) + buffer length
+        // Cursor just past the text, measured in columns since the prefix and input can
+        // hold multibyte or wide characters; it stays inside the border on long input
+        let text_width = Line::from(input_text.as_str()).width() as u16;
         f.set_cursor_position(ratatui::layout::Position::new(
-            input_area.x + app.input_buffer.len() as u16 + 4,
+            (input_area.x + 1 + text_width).min(input_area.right().saturating_sub(2)),
             input_area.y + 1,
         ));
     }
 }
 
+// Who sent a message: the short PeerId it was signed with, then their nickname as an
+// unverified label. The nickname and avatar come from the sender's profile when we have it,
+// otherwise from the message itself. A nickname posing as some other peer's id is flagged.
+fn sender_spans(app: &App, message: &ChatMessage, id_style: Style) -> Vec<Span<'static>> {
+    let theme = app.theme;
+    // Local notices, and messages stored before senders were verified
+    let Some(author) = message.author else {
+        return vec![Span::styled(
+            format!("{}:", short_name(&message.sender)),
+            id_style,
+        )];
+    };
+    let profile = app.profile_of(&author);
+    let id = author.to_string();
+    let id_short = short_name(&id).to_string();
+    let nickname = profile
+        .map(|p| p.nickname.as_str())
+        .filter(|n| !n.is_empty())
+        .unwrap_or(&message.sender);
+
+    let mut spans = vec![];
+    let mut id_style = id_style;
+    if let Some(profile) = profile {
+        let color = profile_color(profile);
+        spans.push(Span::styled(
+            profile.avatar.clone(),
+            Style::default().fg(color),
+        ));
+        id_style = id_style.fg(color);
+    }
+    if nickname == id_short || nickname == id {
+        spans.push(Span::styled(format!("{}:", id_short), id_style));
+        return spans;
+    }
+
+    spans.push(Span::styled(id_short, id_style));
+    spans.push(if claims_other_peer(app, nickname, author) {
+        Span::styled(format!(" ⚠{}:", nickname), Style::default().fg(Color::Red))
+    } else {
+        Span::styled(
+            format!(" ~{}:", nickname),
+            Style::default()
+                .fg(theme.dim)
+                .add_modifier(Modifier::ITALIC),
+        )
+    });
+    spans
+}
+
+fn profile_color(profile: &crate::proto::messages::Profile) -> Color {
+    Color::Rgb(
+        (profile.color >> 16) as u8,
+        (profile.color >> 8) as u8,
+        profile.color as u8,
+    )
+}
+
+// Whether `name` is some other peer's PeerId, or the short form we'd display for one.
+fn claims_other_peer(app: &App, name: &str, author: libp2p::PeerId) -> bool {
+    if let Ok(peer) = name.parse::<libp2p::PeerId>() {
+        return peer != author;
+    }
+    app.peers
+        .iter()
+        .chain(app.local_peer_id.iter())
+        .filter(|p| **p != author)
+        .any(|p| short_name(&p.to_string()) == name)
+}
+
+// Last 8 characters, so PeerId-based names stay recognisable in the narrow pane.
+fn short_name(sender: &str) -> &str {
+    match sender.char_indices().rev().nth(7) {
+        Some((start, _)) => &sender[start..],
+        None => sender,
+    }
+}
+
+// Unix milliseconds as local time using the user's strftime pattern.
+fn format_timestamp(timestamp: u64, format: &str) -> String {
+    use chrono::TimeZone;
+    match chrono::Local.timestamp_millis_opt(timestamp as i64) {
+        chrono::LocalResult::Single(time) => time.format(format).to_string(),
+        _ => "--:--".to_string(),
+    }
+}
+
 fn render_keybind_footer(f: &mut Frame, app: &App) {
+    let theme = app.theme;
     let area = f.area();
 
-    let (conn_dot, conn_color) = connection_indicator(app.peers.len(), app.tick_count);
-
-    let legend = Line::from(vec![
-        Span::styled(" [", Style::default().fg(HUD_DIM)),
-        Span::styled("Q", Style::default().fg(NEON_YELLOW)),
-        Span::styled("]uit  [", Style::default().fg(HUD_DIM)),
-        Span::styled("Enter", Style::default().fg(NEON_YELLOW)),
-        Span::styled("]Chat  │  ", Style::default().fg(HUD_DIM)),
-        Span::styled(format!("{conn_dot}"), Style::default().fg(conn_color)),
-        Span::styled(
-            format!(" {} nodes online", app.peers.len()),
-            Style::default().fg(HUD_TEXT),
-        ),
-    ]);
+    let (conn_dot, conn_color) = connection_indicator(&theme, app.peers.len(), app.tick_count);
+
+    // With a message selected the legend switches to what can be done with it
+    let mut spans = if app.selected_message.is_some() {
+        vec![
+            Span::styled(" ", Style::default().fg(theme.dim)),
+            Span::styled("[ ]", Style::default().fg(theme.highlight)),
+            Span::styled("Select  [", Style::default().fg(theme.dim)),
+            Span::styled("R", Style::default().fg(theme.highlight)),
+            Span::styled("]eply  [", Style::default().fg(theme.dim)),
+            Span::styled("+", Style::default().fg(theme.highlight)),
+            Span::styled("]React  [", Style::default().fg(theme.dim)),
+            Span::styled("E", Style::default().fg(theme.highlight)),
+            Span::styled("]dit  [", Style::default().fg(theme.dim)),
+            Span::styled("X", Style::default().fg(theme.highlight)),
+            Span::styled("]Delete  [", Style::default().fg(theme.dim)),
+            Span::styled("Esc", Style::default().fg(theme.highlight)),
+            Span::styled("]Done  │  ", Style::default().fg(theme.dim)),
+        ]
+    } else {
+        vec![
+            Span::styled(" [", Style::default().fg(theme.dim)),
+            Span::styled("Q", Style::default().fg(theme.highlight)),
+            Span::styled("]uit  [", Style::default().fg(theme.dim)),
+            Span::styled("Enter", Style::default().fg(theme.highlight)),
+            Span::styled("]Chat  [", Style::default().fg(theme.dim)),
+            Span::styled("↑↓", Style::default().fg(theme.highlight)),
+            Span::styled("]Peer  [", Style::default().fg(theme.dim)),
+            Span::styled("D", Style::default().fg(theme.highlight)),
+            Span::styled("]M  [", Style::default().fg(theme.dim)),
+            Span::styled("Tab", Style::default().fg(theme.highlight)),
+            Span::styled("]Room  [", Style::default().fg(theme.dim)),
+            Span::styled("PgUp/Dn", Style::default().fg(theme.highlight)),
+            Span::styled("]Scroll  ", Style::default().fg(theme.dim)),
+            Span::styled("[ ]", Style::default().fg(theme.highlight)),
+            Span::styled("Message  [", Style::default().fg(theme.dim)),
+            Span::styled("←→", Style::default().fg(theme.highlight)),
+            Span::styled("]Globe  [", Style::default().fg(theme.dim)),
+            Span::styled("F", Style::default().fg(theme.highlight)),
+            Span::styled("]ocus  [", Style::default().fg(theme.dim)),
+            Span::styled("M", Style::default().fg(theme.highlight)),
+            Span::styled("]ap  │  ", Style::default().fg(theme.dim)),
+        ]
+    };
+    spans.push(Span::styled(
+        format!("{conn_dot}"),
+        Style::default().fg(conn_color),
+    ));
+    spans.push(Span::styled(
+        format!(" {} nodes online", app.peers.len()),
+        Style::default().fg(theme.text),
+    ));
+    let legend = Line::from(spans);
 
     let footer_area = Rect {
         x: area.x + 1,
@@ -431,14 +926,15 @@ fn render_keybind_footer(f: &mut Frame, app: &App) {
         height: 1,
     };
 
-    let footer = Paragraph::new(legend).style(Style::default().bg(HUD_BG));
+    let footer = Paragraph::new(legend).style(Style::default().bg(theme.background));
     f.render_widget(footer, footer_area);
 }
 
 fn render_boot_splash(f: &mut Frame, app: &mut App) {
+    let theme = app.theme;
     let area = f.area();
 
-    let bg = Block::default().style(Style::default().bg(HUD_BG));
+    let bg = Block::default().style(Style::default().bg(theme.background));
     f.render_widget(bg, area);
 
     // Blinking cursor effect
@@ -446,9 +942,10 @@ fn render_boot_splash(f: &mut Frame, app: &mut App) {
     let input_display = format!(">_ {}{}", app.nickname_buffer, cursor_char);
 
     // Remaining characters indicator
-    let remaining = 8 - app.nickname_buffer.len();
+    let max = crate::profile::MAX_NICKNAME_CHARS;
+    let remaining = max.saturating_sub(app.nickname_buffer.chars().count());
     let char_hint = if app.nickname_buffer.is_empty() {
-        "    (max 8 characters)".to_string()
+        format!("    (max {max} characters)")
     } else {
         format!("    ({remaining} remaining)")
     };
@@ -457,45 +954,49 @@ fn render_boot_splash(f: &mut Frame, app: &mut App) {
         Line::from(""),
         Line::from(Span::styled(
             "  ▄▄▄▄▄▄▄▄▄▄▄▄▄▄▄▄▄▄▄▄▄▄▄▄▄▄▄▄▄▄▄▄▄",
-            Style::default().fg(NEON_CYAN),
+            Style::default().fg(theme.primary),
         )),
         Line::from(""),
         Line::from(vec![
             Span::styled(
                 "       T E R R A ",
-                Style::default().fg(NEON_PINK).add_modifier(Modifier::BOLD),
+                Style::default()
+                    .fg(theme.accent)
+                    .add_modifier(Modifier::BOLD),
             ),
-            Span::styled("- ", Style::default().fg(HUD_DIM)),
+            Span::styled("- ", Style::default().fg(theme.dim)),
             Span::styled(
                 "L I N K",
-                Style::default().fg(NEON_CYAN).add_modifier(Modifier::BOLD),
+                Style::default()
+                    .fg(theme.primary)
+                    .add_modifier(Modifier::BOLD),
             ),
         ]),
         Line::from(""),
         Line::from(Span::styled(
             "    DECENTRALIZED SPATIAL MESH",
-            Style::default().fg(HUD_DIM),
+            Style::default().fg(theme.dim),
         )),
         Line::from(""),
         Line::from(Span::styled(
             "  ▀▀▀▀▀▀▀▀▀▀▀▀▀▀▀▀▀▀▀▀▀▀▀▀▀▀▀▀▀▀▀▀▀",
-            Style::default().fg(NEON_CYAN),
+            Style::default().fg(theme.primary),
         )),
         Line::from(""),
         Line::from(Span::styled(
             "    ENTER YOUR NICKNAME (OPTIONAL)",
-            Style::default().fg(NEON_YELLOW),
+            Style::default().fg(theme.highlight),
         )),
         Line::from(""),
         Line::from(vec![
             Span::styled("    ", Style::default()),
             Span::styled(&input_display, Style::default().fg(Color::White)),
         ]),
-        Line::from(Span::styled(&char_hint, Style::default().fg(HUD_DIM))),
+        Line::from(Span::styled(&char_hint, Style::default().fg(theme.dim))),
         Line::from(""),
         Line::from(Span::styled(
             "    Press [ENTER] to continue",
-            Style::default().fg(HUD_DIM),
+            Style::default().fg(theme.dim),
         )),
         Line::from(""),
     ];
@@ -509,6 +1010,6 @@ fn render_boot_splash(f: &mut Frame, app: &mut App) {
         height: splash_height.min(area.height),
     };
 
-    let splash = Paragraph::new(splash_lines).style(Style::default().bg(HUD_BG));
+    let splash = Paragraph::new(splash_lines).style(Style::default().bg(theme.background));
     f.render_widget(splash, splash_area);
 }